@@ -0,0 +1,513 @@
+//! A bitboard move generator, kept next to the 10x12 mailbox generator of
+//! [`Position::gen_moves`] so the two can be checked against each other.
+//!
+//! A [`BitPosition`] is the same sunfish position as twelve bitboards, still seen
+//! from the side to move: bit 0 is a8 and bit 63 is h1 of the side to move, so
+//! moving "up" the board subtracts 8 and rotating the board reverses the bits.
+//! Sliding pieces use hyperbola quintessence. Moves are produced in mailbox
+//! squares and follow the sunfish rules exactly, including castling by sliding
+//! the rook next to the king and king capture instead of legality checks.
+use crate::board::{
+    BISHOP, EMPTY, KING, KNIGHT, Move, NEWLINE, OPP, PAD, PAWN, Position, QUEEN, ROOK, get_color,
+    is_opp, is_own, pst, swap,
+};
+
+/// The bit square of a mailbox square, or `None` for the padding.
+pub const fn to_sq(i: usize) -> Option<usize> {
+    let (row, col) = (i / 10, i % 10);
+    if 2 <= row && row < 10 && 1 <= col && col < 9 {
+        Some((row - 2) * 8 + col - 1)
+    } else {
+        None
+    }
+}
+/// The mailbox square of a bit square.
+pub const fn to_index(sq: usize) -> usize {
+    (sq / 8 + 2) * 10 + sq % 8 + 1
+}
+
+// Squares reached from each square by the steps in `steps`, or by sliding
+// along them when `slide` is set. Built on the mailbox board, like sunfish.
+const fn targets(steps: &[i32], slide: bool) -> [u64; 64] {
+    let mut res = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        let mut d = 0;
+        while d < steps.len() {
+            let mut j = to_index(sq) as i32;
+            loop {
+                j += steps[d];
+                match to_sq(j as usize) {
+                    Some(t) => res[sq] |= 1 << t,
+                    None => break,
+                }
+                if !slide {
+                    break;
+                }
+            }
+            d += 1;
+        }
+        sq += 1;
+    }
+    res
+}
+const KNIGHT_TARGETS: [u64; 64] = targets(&[-19, -8, 12, 21, 19, 8, -12, -21], false);
+const KING_TARGETS: [u64; 64] = targets(&[-10, 1, 10, -1, -9, 11, 9, -11], false);
+const FILES: [u64; 64] = targets(&[-10, 10], true);
+const RANKS: [u64; 64] = targets(&[-1, 1], true);
+const DIAGONALS: [u64; 64] = targets(&[-9, 9], true);
+const ANTI_DIAGONALS: [u64; 64] = targets(&[-11, 11], true);
+
+// Hyperbola quintessence: attacks of a slider on `sq` along one line.
+fn line_attacks(occupied: u64, sq: usize, line: u64) -> u64 {
+    let o = occupied & line;
+    let r = 1u64 << sq;
+    let forward = o.wrapping_sub(r.wrapping_mul(2));
+    let reverse = o
+        .reverse_bits()
+        .wrapping_sub(r.reverse_bits().wrapping_mul(2))
+        .reverse_bits();
+    (forward ^ reverse) & line
+}
+fn slider_attacks(p: u8, occupied: u64, sq: usize) -> u64 {
+    let straight = || line_attacks(occupied, sq, FILES[sq]) | line_attacks(occupied, sq, RANKS[sq]);
+    let diagonal = || {
+        line_attacks(occupied, sq, DIAGONALS[sq]) | line_attacks(occupied, sq, ANTI_DIAGONALS[sq])
+    };
+    match p {
+        ROOK => straight(),
+        BISHOP => diagonal(),
+        QUEEN => straight() | diagonal(),
+        _ => 0,
+    }
+}
+// Iterates the squares of a bitboard.
+fn squares(mut bb: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bb == 0 {
+            return None;
+        }
+        let sq = bb.trailing_zeros() as usize;
+        bb &= bb - 1;
+        Some(sq)
+    })
+}
+
+/// A sunfish position as bitboards, from the point of view of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitPosition {
+    pub own: [u64; 6], // own pieces, indexed by piece code - PAWN
+    pub opp: [u64; 6], // opponent pieces, indexed the same way
+    pub wc: (bool, bool),
+    pub bc: (bool, bool),
+    pub ep: usize,  // the en passant square, as a mailbox square
+    pub kp: usize,  // the king passant square, as a mailbox square
+    pub color: i32, // 0 if white is to move, 1 if black is
+}
+impl From<&Position> for BitPosition {
+    fn from(pos: &Position) -> BitPosition {
+        let mut res = BitPosition {
+            own: [0; 6],
+            opp: [0; 6],
+            wc: pos.wc,
+            bc: pos.bc,
+            ep: pos.ep,
+            kp: pos.kp,
+            color: get_color(pos),
+        };
+        for sq in 0..64 {
+            res.set(sq, pos.board[to_index(sq)]);
+        }
+        res
+    }
+}
+impl From<&BitPosition> for Position {
+    /// The mailbox position. The score is recomputed from the piece-square tables.
+    fn from(bpos: &BitPosition) -> Position {
+        let newline_col = if bpos.color == 0 { 9 } else { 0 };
+        let mut board = [PAD; 120];
+        for (i, p) in board.iter_mut().enumerate() {
+            if i % 10 == newline_col {
+                *p = NEWLINE;
+            }
+        }
+        let mut score = 0;
+        for sq in 0..64 {
+            let (i, p) = (to_index(sq), bpos.piece_at(sq));
+            board[i] = p;
+            if is_own(p) {
+                score += pst(p)[i];
+            } else if is_opp(p) {
+                score -= pst(swap(p))[119 - i];
+            }
+        }
+        Position {
+            board,
+            score,
+            wc: bpos.wc,
+            bc: bpos.bc,
+            ep: bpos.ep,
+            kp: bpos.kp,
+            hash: 0,
+        }
+        .with_hash()
+    }
+}
+impl BitPosition {
+    /// Own pieces of type `p`.
+    pub fn own_pieces(&self, p: u8) -> u64 {
+        self.own[(p - PAWN) as usize]
+    }
+    /// Opponent pieces of type `p`, given as an own piece code.
+    pub fn opp_pieces(&self, p: u8) -> u64 {
+        self.opp[(p - PAWN) as usize]
+    }
+    /// All own pieces.
+    pub fn own_occupied(&self) -> u64 {
+        self.own.iter().fold(0, |a, b| a | b)
+    }
+    /// All opponent pieces.
+    pub fn opp_occupied(&self) -> u64 {
+        self.opp.iter().fold(0, |a, b| a | b)
+    }
+    /// The board code on a bit square.
+    pub fn piece_at(&self, sq: usize) -> u8 {
+        let bit = 1 << sq;
+        for k in 0..6 {
+            if self.own[k] & bit != 0 {
+                return PAWN + k as u8;
+            }
+            if self.opp[k] & bit != 0 {
+                return (PAWN + k as u8) | OPP;
+            }
+        }
+        EMPTY
+    }
+    // Puts the board code `p` on a bit square, replacing what was there.
+    fn set(&mut self, sq: usize, p: u8) {
+        let bit = 1 << sq;
+        for k in 0..6 {
+            self.own[k] &= !bit;
+            self.opp[k] &= !bit;
+        }
+        if is_own(p) {
+            self.own[(p - PAWN) as usize] |= bit;
+        } else if is_opp(p) {
+            self.opp[(p - (PAWN | OPP)) as usize] |= bit;
+        }
+    }
+    // Like `set`, on a mailbox square. Padding squares can't hold pieces.
+    fn put(&mut self, i: usize, p: u8) {
+        if let Some(sq) = to_sq(i) {
+            self.set(sq, p);
+        }
+    }
+    /// Generates the same pseudo-legal moves as [`Position::gen_moves`], though
+    /// not in the same order.
+    pub fn gen_moves(&self) -> Vec<Move> {
+        let own = self.own_occupied();
+        let opp = self.opp_occupied();
+        let occupied = own | opp;
+        let empty = !occupied;
+        let mut moves = Vec::new();
+        let mut push = |from: usize, to: usize, promote: bool| {
+            let (i, j) = (to_index(from), to_index(to));
+            if promote {
+                for prom in [KNIGHT, BISHOP, ROOK, QUEEN] {
+                    moves.push(Move { i, j, prom });
+                }
+            } else {
+                moves.push(Move { i, j, prom: EMPTY });
+            }
+        };
+        // Pawns push into empty squares, from the first two ranks also by two,
+        // and capture pieces or onto the en passant square.
+        for sq in squares(self.own_pieces(PAWN)) {
+            if sq < 8 {
+                continue;
+            }
+            let up = sq - 8;
+            if empty & 1 << up != 0 {
+                push(sq, up, up < 8);
+                if sq >= 48 && empty & 1 << (up - 8) != 0 {
+                    push(sq, up - 8, false);
+                }
+            }
+            let west = if sq % 8 != 0 { 1 << (sq - 9) } else { 0 };
+            let east = if sq % 8 != 7 { 1 << (sq - 7) } else { 0 };
+            let ep = to_sq(self.ep).map_or(0, |t| 1 << t);
+            for to in squares((west | east) & (opp | ep)) {
+                push(sq, to, to < 8);
+            }
+        }
+        for p in [KNIGHT, KING] {
+            let table = if p == KNIGHT {
+                &KNIGHT_TARGETS
+            } else {
+                &KING_TARGETS
+            };
+            for sq in squares(self.own_pieces(p)) {
+                for to in squares(table[sq] & !own) {
+                    push(sq, to, false);
+                }
+            }
+        }
+        let king = self.own_pieces(KING);
+        let mut castles = Vec::new();
+        for p in [BISHOP, ROOK, QUEEN] {
+            for sq in squares(self.own_pieces(p)) {
+                let attacks = slider_attacks(p, occupied, sq);
+                for to in squares(attacks & !own) {
+                    push(sq, to, false);
+                }
+                // Castling, by sliding the rook next to the king. Like sunfish,
+                // any slider on a1 or h1 will do.
+                for to in squares(attacks & empty) {
+                    let i = to_index(to);
+                    if sq == 56 && self.wc.0 && to % 8 != 7 && king & 1 << (to + 1) != 0 {
+                        castles.push(Move {
+                            i: i + 1,
+                            j: i - 1,
+                            prom: EMPTY,
+                        });
+                    }
+                    if sq == 63 && self.wc.1 && to % 8 != 0 && king & 1 << (to - 1) != 0 {
+                        castles.push(Move {
+                            i: i - 1,
+                            j: i + 1,
+                            prom: EMPTY,
+                        });
+                    }
+                }
+            }
+        }
+        moves.append(&mut castles);
+        moves
+    }
+    /// Plays `mov` like [`Position::domove`] and returns the rotated result.
+    pub fn domove(&self, mov: Move) -> BitPosition {
+        let (a1, h1, a8, h8) = (91, 98, 21, 28);
+        let (n, s) = (-10i32, 10i32);
+        let (i, j) = (mov.i, mov.j);
+        let p = to_sq(i).map_or(EMPTY, |sq| self.piece_at(sq));
+        let mut res = *self;
+        res.ep = 0;
+        res.kp = 0;
+        // Actual move
+        res.put(j, p);
+        res.put(i, EMPTY);
+        // Castling rights, we move the rook or capture the opponent's
+        if i == a1 {
+            res.wc = (false, res.wc.1);
+        }
+        if i == h1 {
+            res.wc = (res.wc.0, false);
+        }
+        if j == a8 {
+            res.bc = (res.bc.0, false);
+        }
+        if j == h8 {
+            res.bc = (false, res.bc.1);
+        }
+        // Castling
+        if p == KING {
+            res.wc = (false, false);
+            if (j as isize - i as isize).abs() == 2 {
+                res.kp = (i + j) / 2;
+                res.put(if j < i { a1 } else { h1 }, EMPTY);
+                res.put(res.kp, ROOK);
+            }
+        }
+        // Pawn promotion, double move and en passant capture
+        if p == PAWN {
+            if a8 <= j && j <= h8 {
+                res.put(j, mov.prom);
+            }
+            if (j as i32) - (i as i32) == 2 * n {
+                res.ep = (i as i32 + n) as usize;
+            }
+            if j == self.ep {
+                res.put((j as i32 + s) as usize, EMPTY);
+            }
+        }
+        res.rotate()
+    }
+    /// Flips the board and the castling rights so the opponent becomes the side to move.
+    pub fn rotate(&self) -> BitPosition {
+        BitPosition {
+            own: self.opp.map(u64::reverse_bits),
+            opp: self.own.map(u64::reverse_bits),
+            wc: self.bc,
+            bc: self.wc,
+            ep: if self.ep == 0 { 0 } else { 119 - self.ep },
+            kp: if self.kp == 0 { 0 } else { 119 - self.kp },
+            color: 1 - self.color,
+        }
+    }
+    /// Whether the side to move can capture the opponent king, see [`crate::board::can_kill_king`].
+    pub fn can_kill_king(&self) -> bool {
+        let king = self.opp_pieces(KING);
+        self.gen_moves().iter().any(|m| {
+            to_sq(m.j).is_some_and(|t| king & 1 << t != 0)
+                || (m.j as isize - self.kp as isize).abs() < 2
+        })
+    }
+}
+
+/// Perft using only the bitboard generator. Returns -1 if `bpos` is illegal.
+pub fn perft_count(bpos: &BitPosition, depth: i32) -> i64 {
+    // Check that we didn't get to an illegal position
+    if bpos.can_kill_king() {
+        return -1;
+    }
+    if depth == 0 {
+        return 1;
+    }
+    let mut res = 0;
+    for mov in bpos.gen_moves() {
+        let cnt = perft_count(&bpos.domove(mov), depth - 1);
+        if cnt != -1 {
+            res += cnt
+        }
+    }
+    res
+}
+
+/// The first position where the mailbox and bitboard generators disagree.
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    pub path: Vec<Move>,          // the moves leading from the root to `pos`
+    pub pos: Position,            // the position where the generators disagree
+    pub mailbox_only: Vec<Move>,  // moves only generated by Position::gen_moves
+    pub bitboard_only: Vec<Move>, // moves only generated by BitPosition::gen_moves
+    pub bad_move: Option<Move>,   // a move both generate but play differently
+}
+
+/// Perft running both generators in lock-step. At every node the move lists and
+/// the positions after each move must agree, otherwise the first difference is
+/// returned. Returns -1 for an illegal position, like the mailbox perft.
+pub fn perft_compare(pos: &Position, depth: i32) -> Result<i64, Box<Divergence>> {
+    let mut path = Vec::new();
+    compare(pos, depth, &mut path)
+}
+fn compare(pos: &Position, depth: i32, path: &mut Vec<Move>) -> Result<i64, Box<Divergence>> {
+    let bpos = BitPosition::from(pos);
+    let divergence = |path: &Vec<Move>, mailbox_only, bitboard_only, bad_move| {
+        Box::new(Divergence {
+            path: path.clone(),
+            pos: *pos,
+            mailbox_only,
+            bitboard_only,
+            bad_move,
+        })
+    };
+    let key = |m: &Move| (m.i, m.j, m.prom);
+    let mut mailbox = pos.gen_moves();
+    let mut bitboard = bpos.gen_moves();
+    mailbox.sort_by_key(key);
+    bitboard.sort_by_key(key);
+    if mailbox != bitboard {
+        let mailbox_only = mailbox.iter().filter(|m| !bitboard.contains(m)).copied();
+        let bitboard_only = bitboard.iter().filter(|m| !mailbox.contains(m)).copied();
+        return Err(divergence(
+            path,
+            mailbox_only.collect(),
+            bitboard_only.collect(),
+            None,
+        ));
+    }
+    if crate::board::can_kill_king(pos) {
+        return Ok(-1);
+    }
+    if depth == 0 {
+        return Ok(1);
+    }
+    let mut res = 0;
+    for mov in mailbox {
+        let next = pos.domove(mov);
+        let bnext = Position::from(&bpos.domove(mov));
+        if (next.board, next.wc, next.bc, next.ep, next.kp, next.hash)
+            != (
+                bnext.board,
+                bnext.wc,
+                bnext.bc,
+                bnext.ep,
+                bnext.kp,
+                bnext.hash,
+            )
+        {
+            return Err(divergence(path, Vec::new(), Vec::new(), Some(mov)));
+        }
+        path.push(mov);
+        let cnt = compare(&next, depth - 1, path)?;
+        path.pop();
+        if cnt != -1 {
+            res += cnt
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    // Black to move, with an en passant square.
+    const BLACK_EP: &str = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+
+    fn assert_perft(fen: &str, depth: i32, nodes: i64) {
+        let pos: Position = fen.parse().unwrap();
+        match perft_compare(&pos, depth) {
+            Ok(n) => assert_eq!(n, nodes, "{}", fen),
+            Err(d) => panic!("generators disagree on {}: {:?}", fen, d),
+        }
+        assert_eq!(perft_count(&BitPosition::from(&pos), depth), nodes);
+    }
+
+    // The counts are the standard ones. perft_compare also checks at every node
+    // that the incrementally updated zobrist key equals one computed from scratch.
+    #[test]
+    fn perft_startpos() {
+        assert_perft(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            3,
+            8902,
+        );
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        assert_perft(KIWIPETE, 2, 2039);
+    }
+
+    #[test]
+    fn perft_position_3() {
+        assert_perft("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4, 43238);
+    }
+
+    #[test]
+    fn perft_promotions() {
+        let pos = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        assert_perft(pos, 3, 9467);
+    }
+
+    #[test]
+    fn perft_black_to_move() {
+        assert_perft(BLACK_EP, 3, 25743);
+    }
+
+    // Null moves don't occur in perft, but the search makes them.
+    #[test]
+    fn null_move_hash() {
+        for fen in [KIWIPETE, BLACK_EP] {
+            let pos: Position = fen.parse().unwrap();
+            for mov in pos.gen_moves() {
+                let null = pos.domove(mov).rotate(true);
+                assert_eq!(null.hash, null.with_hash().hash);
+                let back = null.rotate(true);
+                assert_eq!(back.hash, back.with_hash().hash);
+            }
+        }
+    }
+}
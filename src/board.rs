@@ -0,0 +1,582 @@
+//! Board representation, piece values and the chess rules of a sunfish position.
+//!
+//! A position is always seen from the side to move, which plays "up" the board
+//! with its own pieces (the uppercase letters of sunfish). After every move the
+//! board is rotated.
+use std::str::FromStr;
+
+use crate::zobrist;
+
+// Every square of the board is one byte: an own piece, an opponent piece (the
+// own piece code with OPP set), an empty square or padding around the board.
+pub const EMPTY: u8 = 0;
+pub const PAWN: u8 = 1;
+pub const KNIGHT: u8 = 2;
+pub const BISHOP: u8 = 3;
+pub const ROOK: u8 = 4;
+pub const QUEEN: u8 = 5;
+pub const KING: u8 = 6;
+pub const OPP: u8 = 8;
+pub const PAD: u8 = 16;
+// The padding at the end of each row, only used to tell the colour of a position.
+pub const NEWLINE: u8 = 17;
+
+/// Whether `p` is a piece of the side to move.
+pub const fn is_own(p: u8) -> bool {
+    PAWN <= p && p <= KING
+}
+/// Whether `p` is a piece of the opponent.
+pub const fn is_opp(p: u8) -> bool {
+    PAWN | OPP <= p && p <= KING | OPP
+}
+/// Swaps own and opponent pieces, leaving other squares alone.
+pub const fn swap(p: u8) -> u8 {
+    if is_own(p) || is_opp(p) { p ^ OPP } else { p }
+}
+/// The board code of a sunfish board character, e.g. `'P'`, `'k'`, `'.'`.
+pub fn from_char(c: char) -> u8 {
+    match "PNBRQK".find(c.to_ascii_uppercase()) {
+        Some(index) if c.is_ascii_alphabetic() => {
+            index as u8 + PAWN + if c.is_ascii_lowercase() { OPP } else { 0 }
+        }
+        _ => match c {
+            '\n' => NEWLINE,
+            '.' => EMPTY,
+            _ => PAD,
+        },
+    }
+}
+/// The sunfish board character of a board code.
+pub fn to_char(p: u8) -> char {
+    match p {
+        EMPTY => '.',
+        NEWLINE => '\n',
+        _ if is_own(p) => b"PNBRQK"[(p - PAWN) as usize] as char,
+        _ if is_opp(p) => b"pnbrqk"[(p - (PAWN | OPP)) as usize] as char,
+        _ => ' ',
+    }
+}
+
+//##############################################################################
+// Piece-Square tables. Tune these to change sunfish's behaviour
+//###############################################################################
+
+// With xz compression this whole section takes 652 bytes.
+// That's pretty good given we have 64*6 = 384 values.
+// Though probably we could do better...
+// For one thing, they could easily all fit into int8.
+/// Material value of an own piece.
+pub const fn piece(p: u8) -> i32 {
+    match p {
+        PAWN => 100,
+        KNIGHT => 280,
+        BISHOP => 320,
+        ROOK => 479,
+        QUEEN => 929,
+        KING => 60000,
+        _ => 0,
+    }
+}
+// Indexed by piece code, then board square. Empty squares score nothing.
+#[rustfmt::skip]
+const PST: [[i32; 120]; 7] = [
+    [0; 120],
+    [ // PAWN
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 100, 100, 100, 100, 100, 100, 100, 100, 0,
+        0, 178, 183, 186, 173, 202, 182, 185, 190, 0,
+        0, 107, 129, 121, 144, 140, 131, 144, 107, 0,
+        0, 83, 116, 98, 115, 114, 100, 115, 87, 0,
+        0, 74, 103, 110, 109, 106, 101, 100, 77, 0,
+        0, 78, 109, 105, 89, 90, 98, 103, 81, 0,
+        0, 69, 108, 93, 63, 64, 86, 103, 69, 0,
+        0, 100, 100, 100, 100, 100, 100, 100, 100, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [ // KNIGHT
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 214, 227, 205, 205, 270, 225, 222, 210, 0,
+        0, 277, 274, 380, 244, 284, 342, 276, 266, 0,
+        0, 290, 347, 281, 354, 353, 307, 342, 278, 0,
+        0, 304, 304, 325, 317, 313, 321, 305, 297, 0,
+        0, 279, 285, 311, 301, 302, 315, 282, 280, 0,
+        0, 262, 290, 293, 302, 298, 295, 291, 266, 0,
+        0, 257, 265, 282, 280, 282, 280, 257, 260, 0,
+        0, 206, 257, 254, 256, 261, 245, 258, 211, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [ // BISHOP
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 261, 242, 238, 244, 297, 213, 283, 270, 0,
+        0, 309, 340, 355, 278, 281, 351, 322, 298, 0,
+        0, 311, 359, 288, 361, 372, 310, 348, 306, 0,
+        0, 345, 337, 340, 354, 346, 345, 335, 330, 0,
+        0, 333, 330, 337, 343, 337, 336, 320, 327, 0,
+        0, 334, 345, 344, 335, 328, 345, 340, 335, 0,
+        0, 339, 340, 331, 326, 327, 326, 340, 336, 0,
+        0, 313, 322, 305, 308, 306, 305, 310, 310, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [ // ROOK
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 514, 508, 512, 483, 516, 512, 535, 529, 0,
+        0, 534, 508, 535, 546, 534, 541, 513, 539, 0,
+        0, 498, 514, 507, 512, 524, 506, 504, 494, 0,
+        0, 479, 484, 495, 492, 497, 475, 470, 473, 0,
+        0, 451, 444, 463, 458, 466, 450, 433, 449, 0,
+        0, 437, 451, 437, 454, 454, 444, 453, 433, 0,
+        0, 426, 441, 448, 453, 450, 436, 435, 426, 0,
+        0, 449, 455, 461, 484, 477, 461, 448, 447, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [ // QUEEN
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 935, 930, 921, 825, 998, 953, 1017, 955, 0,
+        0, 943, 961, 989, 919, 949, 1005, 986, 953, 0,
+        0, 927, 972, 961, 989, 1001, 992, 972, 931, 0,
+        0, 930, 913, 951, 946, 954, 949, 916, 923, 0,
+        0, 915, 914, 927, 924, 928, 919, 909, 907, 0,
+        0, 899, 923, 916, 918, 913, 918, 913, 902, 0,
+        0, 893, 911, 929, 910, 914, 914, 908, 891, 0,
+        0, 890, 899, 898, 916, 898, 893, 895, 887, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [ // KING
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 60004, 60054, 60047, 59901, 59901, 60060, 60083, 59938, 0,
+        0, 59968, 60010, 60055, 60056, 60056, 60055, 60010, 60003, 0,
+        0, 59938, 60012, 59943, 60044, 59933, 60028, 60037, 59969, 0,
+        0, 59945, 60050, 60011, 59996, 59981, 60013, 60000, 59951, 0,
+        0, 59945, 59957, 59948, 59972, 59949, 59953, 59992, 59950, 0,
+        0, 59953, 59958, 59957, 59921, 59936, 59968, 59971, 59968, 0,
+        0, 59996, 60003, 59986, 59950, 59943, 59982, 60013, 60004, 0,
+        0, 60017, 60030, 59997, 59986, 60006, 59999, 60040, 60018, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+];
+/// Piece-square table of an own piece, indexed by board square.
+pub fn pst(p: u8) -> &'static [i32; 120] {
+    match p {
+        PAWN..=KING => &PST[p as usize],
+        _ => &PST[EMPTY as usize],
+    }
+}
+//###############################################################################
+// Global constants
+//###############################################################################
+// Our board is represented as 120 bytes, written here as a string. The padding
+// allows for fast detection of moves that don't stay within the board.
+const INITIAL: &str = concat!(
+    "         \n", //   0 -  9
+    "         \n", //  10 - 19
+    " rnbqkbnr\n", //  20 - 29
+    " pppppppp\n", //  30 - 39
+    " ........\n", //  40 - 49
+    " ........\n", //  50 - 59
+    " ........\n", //  60 - 69
+    " ........\n", //  70 - 79
+    " PPPPPPPP\n", //  80 - 89
+    " RNBQKBNR\n", //  90 - 99
+    "         \n", // 100 -109
+    "         \n", // 110 -119
+);
+/// Lists of possible moves for each piece type.
+pub fn directions(p: u8) -> &'static [i32] {
+    const N: i32 = -10;
+    const E: i32 = 1;
+    const S: i32 = 10;
+    const W: i32 = -1;
+    match p {
+        PAWN => &[N, N + N, N + W, N + E],
+        KNIGHT => &[
+            N + N + E,
+            E + N + E,
+            E + S + E,
+            S + S + E,
+            S + S + W,
+            W + S + W,
+            W + N + W,
+            N + N + W,
+        ],
+        BISHOP => &[N + E, N + W, S + E, S + W],
+        ROOK => &[N, E, S, W],
+        QUEEN => &[N, E, S, W, N + E, S + E, S + W, N + W],
+        KING => &[N, E, S, W, N + E, S + E, S + W, N + W],
+        _ => &[],
+    }
+}
+// Mate value must be greater than 8*queen + 2*(rook+knight+bishop)
+// King value is set to twice this value such that if the opponent is
+// 8 queens up, but we got the king, we still exceed MATE_VALUE.
+// When a MATE is detected, we'll set the score to MATE_UPPER - plies to get there
+// E.g. Mate in 3 will be MATE_UPPER - 6
+pub const MATE_LOWER: i32 = piece(KING) - 10 * piece(QUEEN);
+pub const MATE_UPPER: i32 = piece(KING) + 10 * piece(QUEEN);
+
+//###############################################################################
+// Chess logic
+//###############################################################################
+/// A move from square `i` to square `j`, in the 120 square board of the side to move.
+/// `prom` is the promotion piece, or `EMPTY` for none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub i: usize,
+    pub j: usize,
+    pub prom: u8,
+}
+/// A state of a chess game, from the point of view of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub board: [u8; 120], // a 120 square representation of the board
+    pub score: i32,       // the board evaluation
+    pub wc: (bool, bool), // the castling rights, [west/queen side, east/king side]
+    pub bc: (bool, bool), // the opponent castling rights, [west/king side, east/queen side]
+    pub ep: usize,        // the en passant square
+    pub kp: usize,        // the king passant square
+    pub hash: u64,        // the zobrist key, kept up to date by domove and rotate
+}
+impl Position {
+    /// The standard starting position, white to move.
+    pub fn initial() -> Position {
+        let board = board_from_str(INITIAL);
+        Position {
+            board,
+            score: board_score(&board),
+            wc: (true, true),
+            bc: (true, true),
+            ep: 0,
+            kp: 0,
+            hash: 0,
+        }
+        .with_hash()
+    }
+    /// The position with its zobrist key computed from scratch.
+    pub fn with_hash(mut self) -> Position {
+        let color = get_color(&self);
+        self.hash = self
+            .board
+            .iter()
+            .enumerate()
+            .fold(0, |h, (i, &p)| h ^ zobrist::piece(color, i, p))
+            ^ zobrist::castling(self.wc, self.bc)
+            ^ zobrist::en_passant(color, self.ep)
+            ^ zobrist::king_passant(color, self.kp)
+            ^ if color == 0 { 0 } else { zobrist::SIDE };
+        self
+    }
+    /// A 64-bit hash of the position, used as transposition table key.
+    pub fn key(&self) -> u64 {
+        self.hash
+    }
+    /// Flips the board and the castling rights so the opponent becomes the side to move.
+    /// A null move also clears the en passant and king passant squares.
+    pub fn rotate(&self, nullmove: bool) -> Position {
+        let color = get_color(self);
+        let mut hash = self.hash ^ zobrist::SIDE;
+        hash ^= zobrist::castling(self.wc, self.bc) ^ zobrist::castling(self.bc, self.wc);
+        if nullmove {
+            hash ^= zobrist::en_passant(color, self.ep) ^ zobrist::king_passant(color, self.kp);
+        }
+        Position {
+            board: Self::swap_player(self.board),
+            score: -self.score,
+            wc: self.bc,
+            bc: self.wc,
+            ep: if self.ep == 0 || nullmove {
+                0
+            } else {
+                119 - self.ep
+            },
+            kp: if self.kp == 0 || nullmove {
+                0
+            } else {
+                119 - self.kp
+            },
+            hash,
+        }
+    }
+    // Helper function to turn the board around and swap the owner of each piece
+    fn swap_player(mut board: [u8; 120]) -> [u8; 120] {
+        board.reverse();
+        for p in board.iter_mut() {
+            *p = swap(*p);
+        }
+        board
+    }
+    /// Plays `mov` and returns the resulting position, rotated for the opponent.
+    pub fn domove(&self, mov: Move) -> Position {
+        let (a1, h1, a8, h8) = (91, 98, 21, 28);
+        let (n, s) = (-10i32, 10i32);
+        let (i, j) = (mov.i, mov.j);
+        let p = self.board[i];
+        let color = get_color(self);
+        let mut hash = self.hash;
+        let mut put_ = |mut board: [u8; 120], i: usize, p: u8| -> [u8; 120] {
+            hash ^= zobrist::piece(color, i, board[i]) ^ zobrist::piece(color, i, p);
+            board[i] = p;
+            board
+        };
+        // Copy variables and reset ep and kp
+        let mut board = self.board;
+        let mut wc = self.wc;
+        let mut bc = self.bc;
+        let mut ep = 0;
+        let mut kp = 0;
+        //  !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! TODO
+        let score = self.score + self.value(&mov);
+        // Actual move
+        board = put_(board, j, board[i]);
+        board = put_(board, i, EMPTY);
+        // Castling rights, we move the rook or capture the opponent's
+        if i == a1 {
+            wc = (false, wc.1);
+        }
+        if i == h1 {
+            wc = (wc.0, false);
+        }
+        if j == a8 {
+            bc = (bc.0, false);
+        }
+        if j == h8 {
+            bc = (false, bc.1);
+        }
+        // Castling
+        if p == KING {
+            wc = (false, false);
+            if (j as isize - i as isize).abs() == 2 {
+                kp = (i + j) / 2;
+                board = put_(board, if j < i { a1 } else { h1 }, EMPTY);
+                board = put_(board, kp, ROOK);
+            }
+        }
+        // Pawn promotion, double move and en passant capture
+        if p == PAWN {
+            if a8 <= j && j <= h8 {
+                board = put_(board, j, mov.prom);
+            }
+            if (j as i32) - (i as i32) == 2 * n {
+                ep = (i as i32 + n) as usize;
+            }
+            if j == self.ep {
+                board = put_(board, (j as i32 + s) as usize, EMPTY);
+            }
+        }
+        hash ^= zobrist::castling(self.wc, self.bc) ^ zobrist::castling(wc, bc);
+        hash ^= zobrist::en_passant(color, self.ep) ^ zobrist::en_passant(color, ep);
+        hash ^= zobrist::king_passant(color, self.kp) ^ zobrist::king_passant(color, kp);
+        Position {
+            board,
+            score,
+            wc,
+            bc,
+            ep,
+            kp,
+            hash,
+        }
+        .rotate(false)
+    }
+    /// The change in evaluation caused by playing `mov`.
+    pub fn value(&self, mov: &Move) -> i32 {
+        let (a1, h1, a8, h8) = (91, 98, 21, 28);
+        let s = 10i32;
+        let (i, j) = (mov.i, mov.j);
+        let p = self.board[i];
+        let q = self.board[j];
+        // Actual move
+        let mut score = pst(p)[j] - pst(p)[i];
+        // Capture
+        if is_opp(q) {
+            score += pst(swap(q))[119 - j];
+        }
+        // Castling check detection
+        if (j as isize - self.kp as isize).abs() < 2 {
+            score += pst(KING)[119 - j];
+        }
+        // Castling
+        if p == KING && ((i as isize - j as isize).abs() == 2) {
+            score += pst(ROOK)[(i + j) / 2];
+            score -= pst(ROOK)[if j < i { a1 } else { h1 }];
+        }
+        // Special pawn stuff
+        if p == PAWN {
+            if a8 <= j && j <= h8 {
+                score += pst(mov.prom)[j] - pst(PAWN)[j];
+            }
+            if j == self.ep {
+                score += pst(PAWN)[(119 - (j as i32 + s)) as usize]
+            }
+        }
+        score
+    }
+}
+
+/// Converts an algebraic square such as `['e', '4']` to a board index, from white's side.
+pub fn parse(c: [char; 2]) -> i32 {
+    let a1 = 91;
+    let fil = (c[0] as u8 - b'a') as i32;
+    let rank = (c[1].to_digit(10).unwrap() as i32) - 1;
+    a1 + fil - 10 * rank
+}
+// Converts a 120 character sunfish board string to board codes.
+fn board_from_str(board: &str) -> [u8; 120] {
+    board
+        .chars()
+        .map(from_char)
+        .collect::<Vec<u8>>()
+        .try_into()
+        .unwrap()
+}
+// Checks the FEN fields, so building the board can't fail.
+fn check_fen(
+    board: &str,
+    color: &str,
+    castling: &str,
+    enpas: &str,
+    hclock: &str,
+    fclock: &str,
+) -> Result<(), String> {
+    let ranks: Vec<&str> = board.split('/').collect();
+    let width = |rank: &str| {
+        rank.chars()
+            .map(|c| match c {
+                '1'..='8' => c.to_digit(10),
+                _ if "PNBRQKpnbrqk".contains(c) => Some(1),
+                _ => None,
+            })
+            .sum::<Option<u32>>()
+    };
+    if ranks.len() != 8 || ranks.iter().any(|&r| width(r) != Some(8)) {
+        return Err(format!("invalid FEN board {}", board));
+    }
+    if board.matches('K').count() != 1 || board.matches('k').count() != 1 {
+        return Err(format!("FEN board needs one king per side {}", board));
+    }
+    if color != "w" && color != "b" {
+        return Err(format!("invalid FEN side to move {}", color));
+    }
+    if castling.is_empty() || castling != "-" && !castling.chars().all(|c| "KQkq".contains(c)) {
+        return Err(format!("invalid FEN castling rights {}", castling));
+    }
+    // The en passant square is behind the pawn that just moved two squares: on rank 6
+    // with white to move, on rank 3 with black to move.
+    let (ep_rank, pawn, pawn_rank) = if color == "w" {
+        ('6', 'p', ranks[3])
+    } else {
+        ('3', 'P', ranks[4])
+    };
+    let squares: Vec<char> = (pawn_rank.chars())
+        .flat_map(|c| std::iter::repeat_n(c, c.to_digit(10).unwrap_or(1) as usize))
+        .collect();
+    let enpas_chars: Vec<char> = enpas.chars().collect();
+    let pawn_moved = match enpas_chars[..] {
+        [file @ 'a'..='h', rank] => {
+            rank == ep_rank && squares[file as usize - 'a' as usize] == pawn
+        }
+        _ => false,
+    };
+    if enpas != "-" && !pawn_moved {
+        return Err(format!("invalid FEN en passant square {}", enpas));
+    }
+    if hclock.parse::<u32>().is_err() || fclock.parse::<u32>().is_err() {
+        return Err(format!("invalid FEN move counters {} {}", hclock, fclock));
+    }
+    Ok(())
+}
+// The evaluation of a board from scratch, for the side to move.
+fn board_score(board: &[u8; 120]) -> i32 {
+    let own: i32 = (board.iter().enumerate())
+        .filter(|&(_i, &c)| is_own(c))
+        .map(|(i, &c)| pst(c)[i])
+        .sum();
+    let opp: i32 = (board.iter().enumerate())
+        .filter(|&(_i, &c)| is_opp(c))
+        .map(|(i, &c)| pst(swap(c))[119 - i])
+        .sum();
+    own - opp
+}
+/// Builds a position from the six fields of a FEN string, or says what is wrong with them.
+pub fn from_fen(
+    board: &str,
+    color: &str,
+    castling: &str,
+    enpas: &str,
+    hclock: &str,
+    fclock: &str,
+) -> Result<Position, String> {
+    check_fen(board, color, castling, enpas, hclock, fclock)?;
+    let mut iboard = board.to_string();
+    for i in 1..9 {
+        iboard = iboard.replace(&i.to_string(), &".".repeat(i));
+    }
+    iboard = iboard.replace("/", "\n ");
+    iboard = "         \n         \n ".to_string() + &iboard + "\n         \n         \n";
+    let board = board_from_str(&iboard);
+    let wc: (bool, bool) = (castling.contains("Q"), castling.contains("K"));
+    let bc: (bool, bool) = (castling.contains("k"), castling.contains("q"));
+    let ep: usize = if enpas != "-" && enpas.len() == 2 {
+        parse([enpas.chars().next().unwrap(), enpas.chars().nth(1).unwrap()]) as usize
+    } else {
+        0
+    };
+    let pos = Position {
+        board,
+        score: board_score(&board),
+        wc,
+        bc,
+        ep,
+        kp: 0,
+        hash: 0,
+    }
+    .with_hash();
+    Ok(if color == "w" { pos } else { pos.rotate(false) })
+}
+impl FromStr for Position {
+    type Err = String;
+    /// Parses a FEN string, whose move counters may be left out.
+    fn from_str(fen: &str) -> Result<Position, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if !(4..=6).contains(&fields.len()) {
+            return Err(format!("a FEN needs 4 to 6 fields: {}", fen));
+        }
+        let counter = |i: usize, default| fields.get(i).copied().unwrap_or(default);
+        let (board, color, castling, enpas) = (fields[0], fields[1], fields[2], fields[3]);
+        from_fen(
+            board,
+            color,
+            castling,
+            enpas,
+            counter(4, "0"),
+            counter(5, "1"),
+        )
+    }
+}
+/// Returns 0 if white is to move in `pos` and 1 if black is.
+pub fn get_color(pos: &Position) -> i32 {
+    //A slightly hacky way to to get the color from a sunfish position
+    if pos.board[0] == NEWLINE { 1 } else { 0 }
+}
+/// Whether the side to move can capture the opponent king, i.e. the last move was illegal.
+pub fn can_kill_king(pos: &Position) -> bool {
+    // If we just checked for opponent moves capturing the king, we would miss
+    // captures in case of illegal castling.
+    //MATE_LOWER = 60_000 - 10 * 929
+    //return any(pos.value(m) >= MATE_LOWER for m in pos.gen_moves())
+    for m in pos.gen_moves() {
+        if pos.board[m.j] == KING | OPP || ((m.j as isize - pos.kp as isize).abs() < 2) {
+            return true;
+        }
+    }
+    false
+}
@@ -0,0 +1,285 @@
+//! Tokenizes UCI input lines into typed [`UciCommand`]s.
+//!
+//! Parsing never panics: a malformed line gives an error message, which the
+//! front end reports as `info string` before reading the next command.
+use std::str::FromStr;
+
+/// The parameters of a `go` command. Times are in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GoParams {
+    pub wtime: Option<i32>,
+    pub btime: Option<i32>,
+    pub winc: Option<i32>,
+    pub binc: Option<i32>,
+    pub movestogo: Option<i32>,
+    pub movetime: Option<i32>,
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub mate: Option<i32>,
+    pub searchmoves: Vec<String>, // only search these root moves, all if empty
+    pub infinite: bool,
+    pub ponder: bool,
+    // Extensions: a draw search and perft runs, see `draw_loop`, `perft` and `perft_compare`.
+    pub draw: Option<i32>,
+    pub perft: Option<i32>,
+    pub perftcmp: Option<i32>,
+}
+
+/// A command sent by the GUI.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UciCommand {
+    Uci,
+    IsReady,
+    UciNewGame,
+    SetOption {
+        name: String,
+        value: Option<String>,
+    },
+    Position {
+        fen: Option<Vec<String>>, // the six FEN fields, or None for the start position
+        moves: Vec<String>,
+    },
+    Go(GoParams),
+    Stop,
+    PonderHit,
+    Quit,
+}
+
+// The token after `keyword` at `tokens[i]`, parsed as a number.
+fn number<T: FromStr>(tokens: &[&str], i: usize) -> Result<T, String> {
+    let keyword = tokens[i - 1];
+    let Some(token) = tokens.get(i) else {
+        return Err(format!("missing value for {}", keyword));
+    };
+    token
+        .parse::<T>()
+        .map_err(|_| format!("invalid value for {}: {}", keyword, token))
+}
+
+// Like `number`, for depths that must be at least 1.
+fn depth(tokens: &[&str], i: usize) -> Result<i32, String> {
+    match number(tokens, i)? {
+        d if d >= 1 => Ok(d),
+        d => Err(format!("invalid value for {}: {}", tokens[i - 1], d)),
+    }
+}
+
+fn parse_setoption(tokens: &[&str]) -> Result<UciCommand, String> {
+    if tokens.first() != Some(&"name") {
+        return Err("setoption without name".to_string());
+    }
+    let value_at = tokens.iter().position(|&t| t == "value");
+    let name = tokens[1..value_at.unwrap_or(tokens.len())].join(" ");
+    if name.is_empty() {
+        return Err("setoption without name".to_string());
+    }
+    let value = value_at.map(|v| tokens[v + 1..].join(" "));
+    Ok(UciCommand::SetOption { name, value })
+}
+
+fn parse_position(tokens: &[&str]) -> Result<UciCommand, String> {
+    let moves_at = tokens.iter().position(|&t| t == "moves");
+    let moves = match moves_at {
+        Some(m) => tokens[m + 1..].iter().map(|t| t.to_string()).collect(),
+        None => Vec::new(),
+    };
+    let setup = &tokens[..moves_at.unwrap_or(tokens.len())];
+    let fen = match setup.first() {
+        Some(&"startpos") if setup.len() == 1 => None,
+        Some(&"fen") if (5..=7).contains(&setup.len()) => {
+            // The move counters are optional
+            let counters = ["0", "1"].into_iter().skip(setup.len() - 5);
+            let fields = setup[1..].iter().copied().chain(counters);
+            Some(fields.map(|t| t.to_string()).collect())
+        }
+        Some(&"fen") => return Err("position fen needs 4 to 6 fields".to_string()),
+        _ => return Err("position needs startpos or fen".to_string()),
+    };
+    Ok(UciCommand::Position { fen, moves })
+}
+
+const GO_KEYWORDS: [&str; 15] = [
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "movetime",
+    "depth",
+    "nodes",
+    "mate",
+    "searchmoves",
+    "infinite",
+    "ponder",
+    "draw",
+    "perft",
+    "perftcmp",
+];
+
+// Parameters may come in any order, each keyword followed by its value, if any.
+fn parse_go(tokens: &[&str]) -> Result<UciCommand, String> {
+    let mut go = GoParams::default();
+    let mut i = 0;
+    while i < tokens.len() {
+        let keyword = tokens[i];
+        i += 1;
+        match keyword {
+            "wtime" => go.wtime = Some(number(tokens, i)?),
+            "btime" => go.btime = Some(number(tokens, i)?),
+            "winc" => go.winc = Some(number(tokens, i)?),
+            "binc" => go.binc = Some(number(tokens, i)?),
+            "movestogo" => go.movestogo = Some(depth(tokens, i)?),
+            "movetime" => go.movetime = Some(number(tokens, i)?),
+            "depth" => go.depth = Some(depth(tokens, i)?),
+            "nodes" => go.nodes = Some(number(tokens, i)?),
+            "mate" => go.mate = Some(depth(tokens, i)?),
+            "draw" => go.draw = Some(depth(tokens, i)?),
+            "perft" => go.perft = Some(depth(tokens, i)?),
+            "perftcmp" => go.perftcmp = Some(depth(tokens, i)?),
+            "infinite" => go.infinite = true,
+            "ponder" => go.ponder = true,
+            "searchmoves" => {
+                while i < tokens.len() && !GO_KEYWORDS.contains(&tokens[i]) {
+                    go.searchmoves.push(tokens[i].to_string());
+                    i += 1;
+                }
+                continue;
+            }
+            _ => return Err(format!("unknown go parameter: {}", keyword)),
+        }
+        // Skip the value
+        if !["infinite", "ponder"].contains(&keyword) {
+            i += 1;
+        }
+    }
+    Ok(UciCommand::Go(go))
+}
+
+impl FromStr for UciCommand {
+    type Err = String;
+    /// Parses one input line. Tokens are separated by any whitespace.
+    fn from_str(line: &str) -> Result<UciCommand, String> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = tokens.split_first() else {
+            return Err("empty command".to_string());
+        };
+        match command {
+            "uci" => Ok(UciCommand::Uci),
+            "isready" => Ok(UciCommand::IsReady),
+            "ucinewgame" => Ok(UciCommand::UciNewGame),
+            "setoption" => parse_setoption(args),
+            "position" => parse_position(args),
+            "go" => parse_go(args),
+            "stop" => Ok(UciCommand::Stop),
+            "ponderhit" => Ok(UciCommand::PonderHit),
+            "quit" => Ok(UciCommand::Quit),
+            _ => Err(format!("unknown command: {}", command)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<UciCommand, String> {
+        line.parse()
+    }
+
+    #[test]
+    fn go_parameters() {
+        let go = GoParams {
+            wtime: Some(1000),
+            binc: Some(10),
+            nodes: Some(5_000_000_000),
+            searchmoves: vec!["e2e4".to_string(), "d2d4".to_string()],
+            infinite: true,
+            ..GoParams::default()
+        };
+        let line = "go wtime 1000 searchmoves e2e4 d2d4 infinite binc 10 nodes 5000000000";
+        assert_eq!(parse(line), Ok(UciCommand::Go(go)));
+        let go = GoParams {
+            movetime: Some(500),
+            ponder: true,
+            ..GoParams::default()
+        };
+        assert_eq!(parse("go ponder movetime 500"), Ok(UciCommand::Go(go)));
+    }
+
+    #[test]
+    fn malformed_go() {
+        let err = |line| parse(line).unwrap_err();
+        assert_eq!(err("go wtime"), "missing value for wtime");
+        assert_eq!(err("go wtime x"), "invalid value for wtime: x");
+        assert_eq!(err("go depth 0"), "invalid value for depth: 0");
+        assert_eq!(err("go movestogo -1"), "invalid value for movestogo: -1");
+        assert_eq!(err("go nodes -5"), "invalid value for nodes: -5");
+        assert_eq!(err("go fast"), "unknown go parameter: fast");
+    }
+
+    #[test]
+    fn position() {
+        let moves = vec!["e2e4".to_string()];
+        assert_eq!(
+            parse("position startpos moves e2e4"),
+            Ok(UciCommand::Position { fen: None, moves })
+        );
+        // The move counters default to 0 and 1.
+        let fen = "8/8/8/8/8/8/8/K6k w - - 0 1".split(' ').map(String::from);
+        assert_eq!(
+            parse("position fen 8/8/8/8/8/8/8/K6k w - -"),
+            Ok(UciCommand::Position {
+                fen: Some(fen.collect()),
+                moves: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_position() {
+        let err = |line| parse(line).unwrap_err();
+        assert_eq!(err("position"), "position needs startpos or fen");
+        assert_eq!(err("position moves e2e4"), "position needs startpos or fen");
+        assert_eq!(
+            err("position startpos e2e4"),
+            "position needs startpos or fen"
+        );
+        let needs = "position fen needs 4 to 6 fields";
+        assert_eq!(err("position fen 8/8/8/8/8/8/8/K6k w"), needs);
+        assert_eq!(err("position fen 8/8/8/8/8/8/8/K6k w - - 0 1 2"), needs);
+    }
+
+    #[test]
+    fn setoption() {
+        assert_eq!(
+            parse("setoption name Move Overhead value 30"),
+            Ok(UciCommand::SetOption {
+                name: "Move Overhead".to_string(),
+                value: Some("30".to_string()),
+            })
+        );
+        assert_eq!(
+            parse("setoption name Clear Hash"),
+            Ok(UciCommand::SetOption {
+                name: "Clear Hash".to_string(),
+                value: None,
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_setoption() {
+        let err = |line| parse(line).unwrap_err();
+        assert_eq!(err("setoption"), "setoption without name");
+        assert_eq!(err("setoption QS value 1"), "setoption without name");
+        assert_eq!(err("setoption name value 1"), "setoption without name");
+    }
+
+    #[test]
+    fn unknown_and_empty_commands() {
+        assert_eq!(parse("  "), Err("empty command".to_string()));
+        assert_eq!(parse("think"), Err("unknown command: think".to_string()));
+        assert_eq!(parse(" isready \t"), Ok(UciCommand::IsReady));
+        assert_eq!(parse("ucinewgame"), Ok(UciCommand::UciNewGame));
+    }
+}
@@ -0,0 +1,567 @@
+//! An embeddable engine handle: owns the game history and runs searches on a
+//! background thread, streaming [`Info`] records to a callback.
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::board::{MATE_LOWER, MATE_UPPER, Move, Position, get_color};
+use crate::ordering::OrderingStats;
+use crate::search::Searcher;
+use crate::time::{Clock, TimeManager};
+use crate::tt::Bound;
+use crate::uci::{pv, render_move};
+
+/// One search report: the result of a null-window probe of the root, or news
+/// from a long running iteration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Info {
+    pub depth: i32,
+    pub seldepth: i32,  // the most plies searched from the root
+    pub multipv: usize, // which of the best lines this is, from 1
+    pub time: u64,      // milliseconds since the search started
+    pub nodes: u64,
+    pub nps: u64,
+    pub hashfull: usize, // permille of the transposition table in use
+    pub tbhits: u64,     // always 0, we have no endgame tablebases
+    pub kind: InfoKind,
+}
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InfoKind {
+    /// The score of a probe of the root.
+    Probe {
+        score: i32,
+        bound: Bound,    // how the score relates to the true score
+        pv: Vec<String>, // only known unless it is an upper bound
+    },
+    /// The root move being searched, numbered from 1.
+    CurrMove { mov: String, number: usize },
+    /// The counters of an iteration that is taking a while.
+    Progress,
+    /// The cut-offs counted by the move ordering so far, after each iteration.
+    Ordering(OrderingStats),
+}
+impl fmt::Display for Info {
+    // The UCI "info" line for this report.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let InfoKind::CurrMove { mov, number } = &self.kind {
+            return write!(
+                f,
+                "info depth {} currmove {} currmovenumber {}",
+                self.depth, mov, number
+            );
+        }
+        if let InfoKind::Ordering(stats) = &self.kind {
+            return write!(f, "info string ordering depth {} {}", self.depth, stats);
+        }
+        write!(f, "info depth {} seldepth {}", self.depth, self.seldepth)?;
+        if let InfoKind::Probe { .. } = self.kind {
+            write!(f, " multipv {}", self.multipv)?;
+        }
+        write!(
+            f,
+            " time {} nodes {} nps {} hashfull {} tbhits {}",
+            self.time, self.nodes, self.nps, self.hashfull, self.tbhits
+        )?;
+        match &self.kind {
+            InfoKind::Probe { score, bound, pv } => {
+                write!(f, " score {}", format_score(*score))?;
+                match bound {
+                    Bound::Lower => write!(f, " lowerbound pv {}", pv.join(" ")),
+                    Bound::Upper => write!(f, " upperbound"),
+                    Bound::Exact => write!(f, " pv {}", pv.join(" ")),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A search score in UCI terms: `cp <centipawns>`, or `mate <moves>` for a
+/// forced mate, negative when we are the one getting mated.
+pub fn format_score(score: i32) -> String {
+    if score.abs() < MATE_LOWER {
+        return format!("cp {}", score);
+    }
+    // Mate scores are MATE_UPPER less the plies until the king is captured.
+    let plies = MATE_UPPER - score.abs();
+    if score > 0 {
+        format!("mate {}", plies / 2)
+    } else {
+        format!("mate {}", -(plies / 2 - 1))
+    }
+}
+
+// Whether `score` is a mate for us in at most `moves` moves.
+fn mates_within(score: i32, moves: i32) -> bool {
+    score >= MATE_LOWER && (MATE_UPPER - score) / 2 <= moves
+}
+
+// The deepest iteration of an infinite search.
+const MAX_DEPTH: i32 = 100;
+// How often a long iteration reports its progress, and how long the search runs
+// before the root moves are reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+// Turns the state of the searcher into info reports for the callback. It is shared
+// by `think` and the searcher's progress hook.
+struct Reporter<F> {
+    on_info: F,
+    start: Instant, // when the reported times start
+    last_progress: Instant,
+    currmove: Option<(Move, usize)>, // the last root move reported
+    multipv: usize,                  // the line being searched
+    root: Position,
+}
+impl<F: FnMut(&Info)> Reporter<F> {
+    fn report(&mut self, searcher: &Searcher, kind: InfoKind) {
+        let elapsed = self.start.elapsed();
+        let info = Info {
+            depth: searcher.depth,
+            seldepth: searcher.seldepth,
+            multipv: self.multipv,
+            time: (1000.0 * elapsed.as_secs_f64()).round() as u64,
+            nodes: searcher.nodes,
+            nps: if elapsed.as_secs_f64() > 0.0 {
+                (searcher.nodes as f64 / elapsed.as_secs_f64()).round() as u64
+            } else {
+                0
+            },
+            hashfull: searcher.tt.hashfull(),
+            tbhits: 0,
+            kind,
+        };
+        (self.on_info)(&info);
+    }
+    // Called by the searcher while it runs. Only a search that takes a while
+    // reports its root moves, and its counters at most every PROGRESS_INTERVAL.
+    fn progress(&mut self, searcher: &Searcher) {
+        if self.start.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        if let Some((mov, number)) = searcher.currmove
+            && self.currmove != searcher.currmove
+        {
+            self.currmove = searcher.currmove;
+            let mov = render_move(Some(mov), get_color(&self.root) == 0);
+            self.report(searcher, InfoKind::CurrMove { mov, number });
+        }
+        if self.last_progress.elapsed() >= PROGRESS_INTERVAL {
+            self.last_progress = Instant::now();
+            self.report(searcher, InfoKind::Progress);
+        }
+    }
+}
+
+/// What a search is allowed to spend, and which root moves it may play.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub movetime: Option<i32>, // milliseconds
+    pub clock: Option<Clock>,  // the time left on our clock
+    pub overhead: i32,         // milliseconds kept back for communication lag
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub infinite: bool,         // search until stopped, ignoring all limits
+    pub searchmoves: Vec<Move>, // all moves if empty
+    pub multipv: usize,         // the number of best lines to report, 0 counts as 1
+    pub mate: Option<i32>,      // stop once a mate in this many moves is found
+}
+
+/// Iterative deepening search of the last position in `hist`, reporting to `on_info`.
+/// Returns the principal variation, whose first move is the best move.
+///
+/// With `multipv` > 1 every iteration is repeated for each further line, leaving
+/// out the root moves of the lines found before.
+///
+/// While `pondering` is set the search behaves as if infinite. Once it is cleared
+/// (a ponderhit) the limits apply, with the clock starting at that moment.
+pub fn think(
+    searcher: &mut Searcher,
+    hist: &[Position],
+    limits: Limits,
+    pondering: &Arc<AtomicBool>,
+    on_info: impl FnMut(&Info) + Send + 'static,
+) -> Vec<String> {
+    let Limits {
+        movetime,
+        clock,
+        overhead,
+        depth,
+        nodes,
+        infinite,
+        searchmoves,
+        multipv,
+        mate,
+    } = limits;
+    // A mate in n moves is seen by a search of 2n + 1 plies.
+    let mate_depth = mate.map_or(MAX_DEPTH, |n| 2 * n.max(1) + 1);
+    let max_depth = depth.unwrap_or(MAX_DEPTH).min(mate_depth);
+    let pos = &hist[hist.len() - 1];
+    let mut tm = TimeManager::new(movetime, clock, overhead);
+    let mut pondered = pondering.load(Ordering::Relaxed);
+    let limited = !infinite && !pondered;
+    // The root moves the lines are picked from.
+    let candidates = if searchmoves.is_empty() {
+        pos.legal_moves()
+    } else {
+        searchmoves.clone()
+    };
+    // With a single legal move there is nothing to think about, when thinking costs
+    // time. A fixed depth or node count is still searched, for the score.
+    let forced = candidates.len() == 1 && (movetime.is_some() || clock.is_some());
+    // Keep what was learned about this position on earlier moves.
+    searcher.tt.new_search();
+    searcher.ordering.new_search();
+    searcher.nodes = 0;
+    searcher.max_nodes = if limited { nodes } else { None };
+    if pondered && !infinite {
+        searcher.set_pondering(pondering.clone(), tm.hard_limit());
+    } else {
+        searcher.set_deadline(if limited { tm.deadline() } else { None });
+    }
+    let lines = multipv.clamp(1, candidates.len().max(1));
+    let reporter = Arc::new(Mutex::new(Reporter {
+        on_info,
+        start: Instant::now(),
+        last_progress: Instant::now(),
+        currmove: None,
+        multipv: 1,
+        root: *pos,
+    }));
+    let hook = reporter.clone();
+    searcher.on_progress = Some(Box::new(move |searcher| {
+        hook.lock().unwrap().progress(searcher)
+    }));
+    let mut prev: Option<(Option<Move>, i32)> = None;
+    // The principal variation of the last probe of the first line that wasn't an upper bound.
+    let mut last_pv: Vec<Move> = Vec::new();
+    for idepth in 1..MAX_DEPTH + 1 {
+        let mut iteration_score = None;
+        let mut best = None;
+        let mut found: Vec<Move> = Vec::new();
+        for multipv in 1..lines + 1 {
+            reporter.lock().unwrap().multipv = multipv;
+            // Each further line searches the moves not yet shown in a better one.
+            searcher.searchmoves = if multipv == 1 {
+                searchmoves.clone()
+            } else {
+                candidates
+                    .iter()
+                    .filter(|m| !found.contains(m))
+                    .copied()
+                    .collect()
+            };
+            // The move of this line: the first of the last probe that didn't fail low.
+            let mut head = None;
+            let mut table_move = None;
+            for probe in searcher.search(hist.to_vec(), idepth) {
+                // Our max_depth implementation is a bit wasteful.
+                // We never know when we've seen the last at a certain depth
+                // before we get to the next one
+                if probe.depth > max_depth && !infinite && !pondered {
+                    break;
+                }
+                let (score, bound) = (probe.score, probe.bound);
+                if multipv == 1 && (bound != Bound::Upper || iteration_score.is_none()) {
+                    iteration_score = Some(score);
+                }
+                table_move = probe.mov;
+                if let Some(&mov) = probe.pv.first() {
+                    head = Some(mov);
+                    if multipv == 1 {
+                        last_pv = probe.pv.clone();
+                    }
+                }
+                let pv = pv(&probe.pv, pos);
+                reporter
+                    .lock()
+                    .unwrap()
+                    .report(searcher, InfoKind::Probe { score, bound, pv });
+            }
+            if searcher.stopped() {
+                break;
+            }
+            // If all probes failed low, the table still knows a move.
+            let Some(mov) = head.or(table_move) else {
+                break;
+            };
+            if multipv == 1 {
+                best = Some(mov);
+            }
+            found.push(mov);
+        }
+        if searcher.stopped() {
+            break;
+        }
+        let stats = InfoKind::Ordering(searcher.ordering.stats);
+        reporter.lock().unwrap().report(searcher, stats);
+        if pondering.load(Ordering::Relaxed) || infinite {
+            continue;
+        }
+        if pondered {
+            // The opponent played the expected move, our clock started when the
+            // search noticed, or starts now.
+            pondered = false;
+            let start = searcher.ponderhit().unwrap_or_else(Instant::now);
+            tm.restart(start);
+            let mut reporter = reporter.lock().unwrap();
+            reporter.start = start;
+            reporter.last_progress = start;
+            searcher.nodes = 0;
+            searcher.max_nodes = nodes;
+            searcher.set_deadline(tm.deadline());
+        }
+        let score = iteration_score.unwrap_or(0);
+        if idepth >= max_depth || forced || mate.is_some_and(|n| mates_within(score, n)) {
+            break;
+        }
+        // Think longer when the search is unstable.
+        if let Some((prev_best, prev_score)) = prev {
+            tm.update(best != prev_best, score, prev_score);
+        }
+        prev = Some((best, score));
+        // We may not have a move yet at depth = 1
+        if idepth > 1 && tm.soft_exceeded() {
+            break;
+        }
+    }
+    // Infinite and pondering searches may only return once told to.
+    while (infinite || pondering.load(Ordering::Relaxed)) && !searcher.stop.load(Ordering::Relaxed)
+    {
+        thread::sleep(Duration::from_millis(1));
+    }
+    // If all probes failed low, the move in the table is all we have, and without
+    // one any move beats none.
+    if last_pv.is_empty() {
+        let table_move = searcher.tt.get_move(pos.key());
+        let table_move = table_move.filter(|m| candidates.contains(m));
+        last_pv.extend(table_move.or(candidates.first().copied()));
+    }
+    let pv = pv(&last_pv, pos);
+    // Leave the searcher without limits, for synchronous use.
+    searcher.on_progress = None;
+    searcher.max_nodes = None;
+    searcher.set_deadline(None);
+    searcher.searchmoves.clear();
+    pv
+}
+
+/// An engine that can be driven in-process, e.g. from a GUI.
+///
+/// ```no_run
+/// use std::sync::mpsc;
+/// use sunfish_clone_rs::engine::{Engine, Limits};
+///
+/// let mut engine = Engine::new();
+/// let (tx, rx) = mpsc::channel();
+/// let limits = Limits {
+///     infinite: true,
+///     ..Limits::default()
+/// };
+/// engine.go(limits, false, move |info| tx.send(info.clone()).unwrap_or(()), |_| ());
+/// for info in rx.iter().take(3) {
+///     println!("{info}");
+/// }
+/// let best = engine.stop();
+/// ```
+pub struct Engine {
+    history: Vec<Position>,
+    stop: Arc<AtomicBool>,
+    pondering: Arc<AtomicBool>,
+    searcher: Option<Searcher>,
+    worker: Option<JoinHandle<(Searcher, Vec<String>)>>,
+}
+impl Engine {
+    /// A new engine set up at the starting position.
+    pub fn new() -> Engine {
+        let searcher = Searcher::new();
+        Engine {
+            history: vec![Position::initial()],
+            stop: searcher.stop.clone(),
+            pondering: Arc::new(AtomicBool::new(false)),
+            searcher: Some(searcher),
+            worker: None,
+        }
+    }
+    /// The game history, ending with the position to search.
+    pub fn history(&self) -> &[Position] {
+        &self.history
+    }
+    /// Replaces the game history, stopping any running search first.
+    pub fn set_history(&mut self, history: Vec<Position>) {
+        assert!(!history.is_empty(), "history must contain a position");
+        self.stop();
+        self.history = history;
+    }
+    /// Starts a new game at the starting position, resetting all search state.
+    pub fn new_game(&mut self) {
+        self.searcher().new_game();
+        self.history = vec![Position::initial()];
+    }
+    /// Starts searching the current position on a background thread. `on_info` is
+    /// called from that thread with every report and `on_done` with the principal
+    /// variation once the search finishes or is stopped. A running search is stopped first.
+    ///
+    /// With `ponder` the search runs until [`Engine::ponderhit`] or [`Engine::stop`].
+    pub fn go<F, G>(&mut self, limits: Limits, ponder: bool, on_info: F, on_done: G)
+    where
+        F: FnMut(&Info) + Send + 'static,
+        G: FnOnce(&[String]) + Send + 'static,
+    {
+        self.stop();
+        let pondering = self.pondering.clone();
+        self.pondering.store(ponder, Ordering::Relaxed);
+        self.run(
+            move |searcher, hist| think(searcher, hist, limits, &pondering, on_info),
+            on_done,
+        );
+    }
+    /// Runs `job` with the searcher and the game history on the background thread
+    /// that [`Engine::go`] uses, so it is stopped the same way. `on_done` is called
+    /// with the line that `job` returns.
+    pub fn run<J, G>(&mut self, job: J, on_done: G)
+    where
+        J: FnOnce(&mut Searcher, &[Position]) -> Vec<String> + Send + 'static,
+        G: FnOnce(&[String]) + Send + 'static,
+    {
+        self.stop();
+        let mut searcher = self.searcher.take().expect("searcher is idle");
+        let hist = self.history.clone();
+        self.stop.store(false, Ordering::Relaxed);
+        self.worker = Some(thread::spawn(move || {
+            // A bug in the search mustn't take the engine down, the search just ends
+            // without a move.
+            let pv = panic::catch_unwind(AssertUnwindSafe(|| job(&mut searcher, &hist)))
+                .unwrap_or_else(|_| {
+                    searcher.recover();
+                    Vec::new()
+                });
+            on_done(&pv);
+            (searcher, pv)
+        }));
+    }
+    /// The opponent played the move we were pondering on: continue the search
+    /// as a normal one within its limits.
+    pub fn ponderhit(&mut self) {
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+    /// The searcher, for synchronous use. Any running search is stopped first.
+    pub fn searcher(&mut self) -> &mut Searcher {
+        self.stop();
+        self.stop.store(false, Ordering::Relaxed);
+        self.searcher.as_mut().expect("searcher is idle")
+    }
+    /// Whether a search is running.
+    pub fn is_searching(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+    /// Waits for the running search to finish on its own and returns its principal
+    /// variation, whose first move is the best move.
+    pub fn wait(&mut self) -> Option<Vec<String>> {
+        match self.worker.take()?.join() {
+            Ok((searcher, pv)) => {
+                self.searcher = Some(searcher);
+                Some(pv)
+            }
+            // Only `on_done` can get here, having taken the searcher with it.
+            Err(_) => {
+                let mut searcher = Searcher::new();
+                searcher.stop = self.stop.clone();
+                self.searcher = Some(searcher);
+                Some(Vec::new())
+            }
+        }
+    }
+    /// Stops the running search as soon as possible and returns its principal variation.
+    /// Returns `None` if no search was running.
+    pub fn stop(&mut self) -> Option<Vec<String>> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait()
+    }
+}
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{MATE_LOWER, MATE_UPPER};
+
+    // The score of the side to move when the side to move at `ply` has been mated,
+    // as `bound` scores a mate found that many plies from the root.
+    fn mate_at(ply: i32) -> i32 {
+        let mated = -(MATE_UPPER - ply - 2);
+        if ply % 2 == 0 { mated } else { -mated }
+    }
+
+    #[test]
+    fn mate_scores() {
+        // We mate with our move, the opponent is mated at ply 1.
+        assert_eq!(format_score(mate_at(1)), "mate 1");
+        // The opponent mates with their first move.
+        assert_eq!(format_score(mate_at(2)), "mate -1");
+        for n in 1..=20 {
+            assert_eq!(format_score(mate_at(2 * n - 1)), format!("mate {}", n));
+            assert_eq!(format_score(mate_at(2 * n)), format!("mate -{}", n));
+        }
+    }
+
+    #[test]
+    fn mate_search_stops_at_the_mate() {
+        let pos: Position = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1".parse().unwrap();
+        let limits = Limits {
+            mate: Some(1),
+            ..Limits::default()
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        let pondering = Arc::new(AtomicBool::new(false));
+        let on_info = move |info: &Info| tx.send(info.clone()).unwrap();
+        let pv = think(&mut Searcher::new(), &[pos], limits, &pondering, on_info);
+        assert_eq!(pv.first().map(String::as_str), Some("a1a8"));
+        let last = rx.try_iter().last().unwrap();
+        assert!(last.depth <= 3, "searched on to depth {}", last.depth);
+    }
+
+    #[test]
+    fn lines_go_past_table_cutoffs() {
+        let start = Position::initial();
+        let hist = vec![start, start.domove(start.legal_moves()[0])];
+        let limits = Limits {
+            depth: Some(8),
+            ..Limits::default()
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        let pondering = Arc::new(AtomicBool::new(false));
+        let on_info = move |info: &Info| tx.send(info.clone()).unwrap();
+        think(&mut Searcher::new(), &hist, limits, &pondering, on_info);
+        for info in rx.try_iter() {
+            if let InfoKind::Probe { bound, pv, .. } = info.kind
+                && bound != Bound::Upper
+            {
+                let min = info.depth.min(3) as usize;
+                assert!(pv.len() >= min, "depth {} line {:?}", info.depth, pv);
+            }
+        }
+    }
+
+    #[test]
+    fn centipawn_scores() {
+        assert_eq!(format_score(0), "cp 0");
+        assert_eq!(format_score(35), "cp 35");
+        assert_eq!(format_score(-120), "cp -120");
+        assert_eq!(
+            format_score(MATE_LOWER - 1),
+            format!("cp {}", MATE_LOWER - 1)
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! A Rust clone of the [sunfish](https://github.com/thomasahle/sunfish) chess engine.
+//!
+//! - [`board`] holds the 10x12 board, piece-square tables and move execution.
+//! - [`movegen`] generates pseudo-legal and legal moves for a position.
+//! - [`bitboard`] is a bitboard move generator, checked against [`movegen`] by perft.
+//! - [`search`] contains the MTD-bi searcher.
+//! - [`pvs`] is a principal variation search, selectable instead of MTD-bi.
+//! - [`ordering`] keeps the killer, history and countermove tables that order quiet moves.
+//! - [`tt`] is the fixed-size transposition table used by the searcher.
+//! - [`zobrist`] has the keys for hashing positions incrementally.
+//! - [`time`] decides how long to think about a move.
+//! - [`engine`] wraps the searcher in a handle that searches on a background thread.
+//! - [`command`] parses UCI input lines into typed commands.
+//! - [`options`] is the registry of UCI options.
+//! - [`uci`] is the UCI front end used by the binary.
+pub mod bitboard;
+pub mod board;
+pub mod command;
+pub mod engine;
+pub mod movegen;
+pub mod options;
+pub mod ordering;
+pub mod pvs;
+pub mod search;
+pub mod time;
+pub mod tt;
+pub mod uci;
+pub mod zobrist;
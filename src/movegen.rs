@@ -0,0 +1,207 @@
+//! Pseudo-legal and legal move generation on the 10x12 mailbox board.
+use crate::board::{
+    BISHOP, EMPTY, KING, KNIGHT, Move, OPP, PAD, PAWN, Position, QUEEN, ROOK, directions, is_opp,
+    is_own, swap,
+};
+
+impl Position {
+    /// Generates all pseudo-legal moves for the side to move.
+    /// Moves that leave the own king in check are included; the search
+    /// discovers them by the king capture that follows.
+    pub fn gen_moves(&self) -> Vec<Move> {
+        let (a1, h1, a8, h8) = (91, 98, 21, 28);
+        let (n, e, w) = (-10, 1, -1);
+
+        let mut moves = Vec::new();
+        for i in 0..120 {
+            let p = self.board[i];
+            if !is_own(p) {
+                continue; // skip empty squares and opponent pieces
+            }
+            let directions = directions(p);
+            for &d in directions {
+                let mut j = i;
+                loop {
+                    j = (j as i32 + d) as usize;
+                    let q = self.board[j];
+                    // Stay inside the board, and off friendly pieces
+                    if is_own(q) || q >= PAD {
+                        break; // skip moves that capture own pieces
+                    }
+                    if p == PAWN {
+                        if [n, n + n].contains(&d) && q != EMPTY {
+                            break;
+                        }
+                        if d == (n + n)
+                            && (i < (a1 as i32 + n) as usize
+                                || self.board[(i as i32 + n) as usize] != EMPTY)
+                        {
+                            break;
+                        }
+                        if [n + w, n + e].contains(&d) && q == EMPTY && ![self.ep].contains(&j) {
+                            break;
+                        }
+                        // If we move to the last row, we can be anything
+                        if a8 <= j && j <= h8 {
+                            for prom in [KNIGHT, BISHOP, ROOK, QUEEN] {
+                                moves.push(Move { i, j, prom });
+                            }
+                            break;
+                        }
+                    }
+                    // Move it
+                    moves.push(Move { i, j, prom: EMPTY });
+                    // Stop crawlers from sliding, and sliding after captures
+                    if [PAWN, KNIGHT, KING].contains(&p) || is_opp(q) {
+                        break;
+                    }
+                    // Castling, by sliding the rook next to the king
+                    if i == a1 && self.board[(j as i32 + e) as usize] == KING && self.wc.0 {
+                        moves.push(Move {
+                            i: (j as i32 + e) as usize,
+                            j: (j as i32 + w) as usize,
+                            prom: EMPTY,
+                        })
+                    }
+                    if i == h1 && self.board[(j as i32 + w) as usize] == KING && self.wc.1 {
+                        moves.push(Move {
+                            i: (j as i32 + w) as usize,
+                            j: (j as i32 + e) as usize,
+                            prom: EMPTY,
+                        })
+                    }
+                }
+            }
+        }
+        moves
+    }
+    /// Whether any opponent piece attacks square `sq`.
+    pub fn is_attacked(&self, sq: usize) -> bool {
+        let (n, e, w) = (-10i32, 1i32, -1i32);
+        let at = |d: i32| self.board[(sq as i32 + d) as usize];
+        // Opponent pawns move south, so they attack from the north
+        if [n + w, n + e].iter().any(|&d| at(d) == PAWN | OPP) {
+            return true;
+        }
+        for p in [KNIGHT, KING] {
+            if directions(p).iter().any(|&d| at(d) == p | OPP) {
+                return true;
+            }
+        }
+        for (p, sliders) in [(ROOK, [ROOK, QUEEN]), (BISHOP, [BISHOP, QUEEN])] {
+            for &d in directions(p) {
+                let mut j = sq as i32 + d;
+                while self.board[j as usize] == EMPTY {
+                    j += d;
+                }
+                let q = self.board[j as usize];
+                if is_opp(q) && sliders.contains(&swap(q)) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+    /// Whether the side to move is in check.
+    pub fn is_check(&self) -> bool {
+        match self.board.iter().position(|&p| p == KING) {
+            Some(k) => self.is_attacked(k),
+            None => false,
+        }
+    }
+    /// Generates the legal moves for the side to move: the pseudo-legal moves
+    /// that don't leave the own king in check, and castling only when the king
+    /// doesn't start in, pass through or end up in check.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = self.gen_moves();
+        moves.retain(|m| {
+            let castling = self.board[m.i] == KING && (m.j as isize - m.i as isize).abs() == 2;
+            if castling && (self.is_attacked(m.i) || self.is_attacked((m.i + m.j) / 2)) {
+                return false;
+            }
+            // After the move the opponent is to move, so rotate back to test our king
+            !self.domove(*m).rotate(true).is_check()
+        });
+        moves
+    }
+    /// Whether the side to move is checkmated.
+    pub fn is_checkmate(&self) -> bool {
+        self.is_check() && self.legal_moves().is_empty()
+    }
+    /// Whether the side to move is stalemated.
+    pub fn is_stalemate(&self) -> bool {
+        !self.is_check() && self.legal_moves().is_empty()
+    }
+    /// Counts the legal move sequences of `depth` plies, to compare with known
+    /// perft results.
+    pub fn perft(&self, depth: i32) -> u64 {
+        if depth <= 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        moves.iter().map(|&m| self.domove(m).perft(depth - 1)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::{EMPTY, Move, Position, parse};
+
+    fn fen(fen: &str) -> Position {
+        fen.parse().unwrap()
+    }
+    // A move of white, who is to move in all positions here.
+    fn mov(uci: &str) -> Move {
+        let c: Vec<char> = uci.chars().collect();
+        Move {
+            i: parse([c[0], c[1]]) as usize,
+            j: parse([c[2], c[3]]) as usize,
+            prom: EMPTY,
+        }
+    }
+
+    #[test]
+    fn perft_startpos() {
+        let pos = Position::initial();
+        assert_eq!([1, 2, 3].map(|d| pos.perft(d)), [20, 400, 8902]);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        let pos = fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!([1, 2].map(|d| pos.perft(d)), [48, 2039]);
+    }
+
+    #[test]
+    fn perft_position_3() {
+        let pos = fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+        assert_eq!([1, 2, 3].map(|d| pos.perft(d)), [14, 191, 2812]);
+    }
+
+    #[test]
+    fn en_passant_discovering_check_is_illegal() {
+        // Taking c6 en passant leaves the rank of the king open to the rook.
+        let pos = fen("8/8/8/KPp4r/8/8/8/7k w - c6 0 1");
+        assert!(pos.gen_moves().contains(&mov("b5c6")));
+        assert!(!pos.legal_moves().contains(&mov("b5c6")));
+        assert!(pos.legal_moves().contains(&mov("b5b6")));
+    }
+
+    #[test]
+    fn no_castling_through_check() {
+        let pos = fen("5r1k/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert!(pos.gen_moves().contains(&mov("e1g1")));
+        assert!(!pos.legal_moves().contains(&mov("e1g1")));
+        assert!(pos.legal_moves().contains(&mov("e1c1")));
+    }
+
+    #[test]
+    fn checkmate_and_stalemate() {
+        let mated = fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert!(mated.is_check() && mated.is_checkmate() && !mated.is_stalemate());
+        let stalemated = fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert!(!stalemated.is_check() && stalemated.is_stalemate() && !stalemated.is_checkmate());
+        let start = Position::initial();
+        assert!(!start.is_check() && !start.is_checkmate() && !start.is_stalemate());
+    }
+}
@@ -0,0 +1,265 @@
+//! The UCI options of the engine: declared once, printed for the `uci` command
+//! and checked and stored on `setoption`.
+use std::fmt;
+
+/// The type of an option, with its default and allowed values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptionKind {
+    Spin { default: i32, min: i32, max: i32 },
+    Check { default: bool },
+    Combo { default: String, vars: Vec<String> },
+    String { default: String },
+    Button,
+}
+
+/// The current value of an option. Buttons have no value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptionValue {
+    Spin(i32),
+    Check(bool),
+    String(String), // combo and string options
+    Button,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UciOption {
+    pub name: String,
+    pub kind: OptionKind,
+    pub value: OptionValue,
+}
+impl UciOption {
+    pub fn new(name: &str, kind: OptionKind) -> UciOption {
+        let value = match &kind {
+            OptionKind::Spin { default, .. } => OptionValue::Spin(*default),
+            OptionKind::Check { default } => OptionValue::Check(*default),
+            OptionKind::Combo { default, .. } | OptionKind::String { default } => {
+                OptionValue::String(default.clone())
+            }
+            OptionKind::Button => OptionValue::Button,
+        };
+        UciOption {
+            name: name.to_string(),
+            kind,
+            value,
+        }
+    }
+    // Parses a setoption value for this option.
+    fn parse(&self, value: &str) -> Result<OptionValue, String> {
+        let invalid = || format!("invalid value for {}: {}", self.name, value);
+        match &self.kind {
+            OptionKind::Spin { min, max, .. } => match value.parse::<i32>() {
+                Ok(v) if (*min..=*max).contains(&v) => Ok(OptionValue::Spin(v)),
+                Ok(_) => Err(format!(
+                    "value for {} must be between {} and {}: {}",
+                    self.name, min, max, value
+                )),
+                Err(_) => Err(invalid()),
+            },
+            OptionKind::Check { .. } => match value {
+                "true" => Ok(OptionValue::Check(true)),
+                "false" => Ok(OptionValue::Check(false)),
+                _ => Err(invalid()),
+            },
+            OptionKind::Combo { vars, .. } => {
+                match vars.iter().find(|v| v.eq_ignore_ascii_case(value)) {
+                    Some(v) => Ok(OptionValue::String(v.clone())),
+                    None => Err(invalid()),
+                }
+            }
+            // The UCI way of sending an empty string
+            OptionKind::String { .. } if value == "<empty>" => {
+                Ok(OptionValue::String(String::new()))
+            }
+            OptionKind::String { .. } => Ok(OptionValue::String(value.to_string())),
+            OptionKind::Button => Ok(OptionValue::Button),
+        }
+    }
+}
+impl fmt::Display for UciOption {
+    // The "option" line sent in reply to "uci".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+        match &self.kind {
+            OptionKind::Spin { default, min, max } => {
+                write!(f, "spin default {} min {} max {}", default, min, max)
+            }
+            OptionKind::Check { default } => write!(f, "check default {}", default),
+            OptionKind::Combo { default, vars } => {
+                write!(f, "combo default {}", default)?;
+                vars.iter().try_for_each(|v| write!(f, " var {}", v))
+            }
+            OptionKind::String { default } if default.is_empty() => {
+                write!(f, "string default <empty>")
+            }
+            OptionKind::String { default } => write!(f, "string default {}", default),
+            OptionKind::Button => write!(f, "button"),
+        }
+    }
+}
+
+/// All options of the engine, in the order they are printed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    options: Vec<UciOption>,
+}
+impl Options {
+    pub fn new() -> Options {
+        Options::default()
+    }
+    /// Declares an option.
+    pub fn add(&mut self, name: &str, kind: OptionKind) {
+        self.options.push(UciOption::new(name, kind));
+    }
+    /// The option called `name`, ignoring case like UCI does.
+    pub fn get(&self, name: &str) -> Option<&UciOption> {
+        self.options
+            .iter()
+            .find(|o| o.name.eq_ignore_ascii_case(name))
+    }
+    /// The value of a spin option, 0 if there is none.
+    pub fn spin(&self, name: &str) -> i32 {
+        match self.get(name).map(|o| &o.value) {
+            Some(OptionValue::Spin(v)) => *v,
+            _ => 0,
+        }
+    }
+    /// The value of a check option, false if there is none.
+    pub fn check(&self, name: &str) -> bool {
+        matches!(
+            self.get(name).map(|o| &o.value),
+            Some(OptionValue::Check(true))
+        )
+    }
+    /// The value of a combo or string option, empty if there is none.
+    pub fn string(&self, name: &str) -> &str {
+        match self.get(name).map(|o| &o.value) {
+            Some(OptionValue::String(v)) => v,
+            _ => "",
+        }
+    }
+    /// Sets an option from a setoption command. Returns the option, so the caller
+    /// can act on the change, or a description of what is wrong.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<&UciOption, String> {
+        let Some(option) = self
+            .options
+            .iter_mut()
+            .find(|o| o.name.eq_ignore_ascii_case(name))
+        else {
+            return Err(format!("unknown option: {}", name));
+        };
+        let value = match (value, &option.kind) {
+            (_, OptionKind::Button) => OptionValue::Button,
+            (Some(value), _) => option.parse(value)?,
+            (None, _) => return Err(format!("missing value for {}", option.name)),
+        };
+        option.value = value;
+        Ok(option)
+    }
+    /// Iterates the options, e.g. to print them.
+    pub fn iter(&self) -> impl Iterator<Item = &UciOption> {
+        self.options.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> Options {
+        let mut options = Options::new();
+        options.add(
+            "Move Overhead",
+            OptionKind::Spin {
+                default: 10,
+                min: 0,
+                max: 5000,
+            },
+        );
+        options.add("Ponder", OptionKind::Check { default: false });
+        let vars = ["MTD-bi", "PVS"].map(String::from).to_vec();
+        options.add(
+            "Search",
+            OptionKind::Combo {
+                default: vars[0].clone(),
+                vars,
+            },
+        );
+        options.add(
+            "Book File",
+            OptionKind::String {
+                default: String::new(),
+            },
+        );
+        options.add("Clear Hash", OptionKind::Button);
+        options
+    }
+
+    #[test]
+    fn spin_bounds() {
+        let mut options = options();
+        assert!(options.set("Move Overhead", Some("0")).is_ok());
+        assert!(options.set("Move Overhead", Some("5000")).is_ok());
+        assert_eq!(
+            options.set("Move Overhead", Some("5001")),
+            Err("value for Move Overhead must be between 0 and 5000: 5001".to_string())
+        );
+        assert_eq!(
+            options.set("Move Overhead", Some("-1")),
+            Err("value for Move Overhead must be between 0 and 5000: -1".to_string())
+        );
+        assert_eq!(
+            options.set("Move Overhead", Some("99999999999")),
+            Err("invalid value for Move Overhead: 99999999999".to_string())
+        );
+        // A rejected value leaves the last good one.
+        assert_eq!(options.spin("Move Overhead"), 5000);
+    }
+
+    #[test]
+    fn values_by_type() {
+        let mut options = options();
+        assert_eq!(options.spin("Move Overhead"), 10);
+        // Names are matched ignoring case.
+        options.set("move overhead", Some("30")).unwrap();
+        assert_eq!(options.spin("Move Overhead"), 30);
+        options.set("Ponder", Some("true")).unwrap();
+        assert!(options.check("Ponder"));
+        assert!(options.set("Ponder", Some("yes")).is_err());
+        options.set("Search", Some("pvs")).unwrap();
+        assert_eq!(options.string("Search"), "PVS");
+        assert!(options.set("Search", Some("alpha-beta")).is_err());
+        options.set("Book File", Some("my book.bin")).unwrap();
+        assert_eq!(options.string("Book File"), "my book.bin");
+        options.set("Book File", Some("<empty>")).unwrap();
+        assert_eq!(options.string("Book File"), "");
+        assert!(options.set("Clear Hash", None).is_ok());
+    }
+
+    #[test]
+    fn bad_setoption() {
+        let mut options = options();
+        assert_eq!(
+            options.set("Threads", Some("4")),
+            Err("unknown option: Threads".to_string())
+        );
+        assert_eq!(
+            options.set("Ponder", None),
+            Err("missing value for Ponder".to_string())
+        );
+    }
+
+    #[test]
+    fn option_lines() {
+        let lines: Vec<String> = options().iter().map(|o| o.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "option name Move Overhead type spin default 10 min 0 max 5000",
+                "option name Ponder type check default false",
+                "option name Search type combo default MTD-bi var MTD-bi var PVS",
+                "option name Book File type string default <empty>",
+                "option name Clear Hash type button",
+            ]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Move ordering: the hash move first, then captures and promotions by value,
+//! then the quiet moves in the order earlier cut-offs suggest. Those are the
+//! killer moves of the ply, the countermove to the opponent's last move and a
+//! butterfly history of quiet moves indexed by their from and to squares.
+use std::fmt;
+
+use crate::board::{Move, Position};
+use crate::search::quiet;
+
+// Killer moves kept per ply, the most recent first.
+const KILLERS: usize = 2;
+// Once a history score passes this, all of them are halved, so recent cut-offs count most.
+const HISTORY_MAX: i32 = 1 << 20;
+const SQUARES: usize = 120;
+
+/// How a move is ordered, from the moves searched first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MoveKind {
+    /// The best move stored in the table, called the killer by `getmoves`.
+    Hash,
+    /// A capture or promotion.
+    Capture,
+    /// A quiet move that caused a cut-off at the same ply.
+    Killer,
+    /// The quiet move that last refuted the opponent's previous move.
+    Countermove,
+    /// Any other quiet move.
+    Quiet,
+}
+
+/// Counts of the cut-offs in the search, for tuning the move ordering.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OrderingStats {
+    pub cutoffs: u64, // nodes where a move reached the bound
+    pub first: u64,   // of those, where it was the first move searched
+    // The cut-offs by each kind of move
+    pub hash: u64,
+    pub captures: u64,
+    pub killers: u64,
+    pub countermoves: u64,
+    pub quiets: u64,
+}
+impl fmt::Display for OrderingStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cutoffs {} first {} hash {} captures {} killers {} countermoves {} quiets {}",
+            self.cutoffs,
+            self.first,
+            self.hash,
+            self.captures,
+            self.killers,
+            self.countermoves,
+            self.quiets
+        )
+    }
+}
+
+/// What the search has learned about ordering quiet moves.
+pub struct MoveOrdering {
+    killers: Vec<[Option<Move>; KILLERS]>, // by ply
+    history: Vec<i32>,                     // by from and to square
+    countermoves: Vec<Option<Move>>,       // by the from and to square of the previous move
+    pub stats: OrderingStats,
+}
+// The index of a move in the butterfly tables.
+fn index(mov: &Move) -> usize {
+    mov.i * SQUARES + mov.j
+}
+impl MoveOrdering {
+    pub fn new() -> MoveOrdering {
+        MoveOrdering {
+            killers: Vec::new(),
+            history: vec![0; SQUARES * SQUARES],
+            countermoves: vec![None; SQUARES * SQUARES],
+            stats: OrderingStats::default(),
+        }
+    }
+    /// Forgets everything, e.g. for a new game.
+    pub fn clear(&mut self) {
+        *self = MoveOrdering::new();
+    }
+    /// Starts the search of a new move. The killers are dropped, as the plies now
+    /// mean other positions, and the history fades. The statistics start again.
+    pub fn new_search(&mut self) {
+        self.killers.clear();
+        self.history.iter_mut().for_each(|h| *h /= 2);
+        self.stats = OrderingStats::default();
+    }
+    /// The history score of a quiet move.
+    pub fn history(&self, mov: &Move) -> i32 {
+        self.history[index(mov)]
+    }
+    /// The kind of `mov` in `pos` at `ply`, played in reply to `prev`.
+    pub fn kind(
+        &self,
+        pos: &Position,
+        mov: &Move,
+        hash: Option<Move>,
+        ply: i32,
+        prev: Option<Move>,
+    ) -> MoveKind {
+        if Some(*mov) == hash {
+            MoveKind::Hash
+        } else if !quiet(pos, mov) {
+            MoveKind::Capture
+        } else if self.killer_slot(mov, ply).is_some() {
+            MoveKind::Killer
+        } else if prev.is_some_and(|p| self.countermoves[index(&p)] == Some(*mov)) {
+            MoveKind::Countermove
+        } else {
+            MoveKind::Quiet
+        }
+    }
+    fn killer_slot(&self, mov: &Move, ply: i32) -> Option<usize> {
+        let killers = self.killers.get(ply as usize)?;
+        killers.iter().position(|k| *k == Some(*mov))
+    }
+    /// Sorts moves, given with their value and kind in order of value, by kind.
+    /// Captures stay in order of value, killers in the order they were found and
+    /// other quiet moves go by history, then value.
+    pub fn sort(&self, moves: &mut [(i32, Move, MoveKind)], ply: i32) {
+        moves.sort_by_key(|(val, mov, kind)| {
+            let within = match kind {
+                MoveKind::Capture => -val,
+                MoveKind::Killer => self.killer_slot(mov, ply).unwrap_or(0) as i32,
+                MoveKind::Quiet => -self.history(mov),
+                MoveKind::Hash | MoveKind::Countermove => 0,
+            };
+            (*kind, within)
+        });
+    }
+    /// Counts a cut-off by a move of the given kind, after `number` other moves
+    /// were searched.
+    pub fn count(&mut self, kind: MoveKind, number: usize) {
+        self.stats.cutoffs += 1;
+        self.stats.first += u64::from(number == 0);
+        *match kind {
+            MoveKind::Hash => &mut self.stats.hash,
+            MoveKind::Capture => &mut self.stats.captures,
+            MoveKind::Killer => &mut self.stats.killers,
+            MoveKind::Countermove => &mut self.stats.countermoves,
+            MoveKind::Quiet => &mut self.stats.quiets,
+        } += 1;
+    }
+    /// Learns from the quiet move `mov` reaching the bound at `ply` with `depth`
+    /// left, played in reply to `prev`.
+    pub fn learn(&mut self, mov: Move, ply: i32, depth: i32, prev: Option<Move>) {
+        let ply = ply as usize;
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; KILLERS]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(mov) {
+            killers.rotate_right(1);
+            killers[0] = Some(mov);
+        }
+        if let Some(prev) = prev {
+            self.countermoves[index(&prev)] = Some(mov);
+        }
+        let history = &mut self.history[index(&mov)];
+        *history += depth * depth;
+        if *history > HISTORY_MAX {
+            self.history.iter_mut().for_each(|h| *h /= 2);
+        }
+    }
+}
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,252 @@
+//! Principal variation search: alpha-beta with a full window along the
+//! principal variation and null windows elsewhere, started from an aspiration
+//! window around the score of the previous iteration.
+//!
+//! It uses the move ordering, pruning and table of the MTD-bi search in
+//! [`crate::search`], so the two can be compared in matches.
+use std::cmp::{max, min};
+
+use crate::board::{MATE_LOWER, MATE_UPPER, Move, Position};
+use crate::ordering::MoveKind;
+use crate::search::{Probe, Searcher, from_tt, to_tt};
+use crate::tt::Bound;
+
+// Half the width of the first aspiration window, doubled on every re-search.
+const ASPIRATION_WINDOW: i32 = 25;
+// Outside of all scores, so a search in (-INF, INF) is always exact.
+const INF: i32 = MATE_UPPER + 1;
+
+impl Searcher {
+    /// Alpha-beta search of `pos` in the window (`alpha`, `beta`) to the given depth.
+    /// Returns the score if it is inside the window, otherwise a bound beyond the
+    /// side it fell out of.
+    pub fn pvs(
+        &mut self,
+        pos: &Position,
+        mut alpha: i32,
+        beta: i32,
+        depth: i32,
+        can_null: bool,
+    ) -> i32 {
+        if let Some(score) = self.enter(pos) {
+            return score;
+        }
+        let ply = self.ply as usize;
+        // Checks are extended, like in `bound`.
+        let in_check = pos.is_check();
+        let depth = max(depth, 0) + self.extension(in_check);
+        let pv_node = beta - alpha > 1;
+        // Repetitions are draws, except at the root, see `bound`.
+        if can_null && depth > 0 && self.history.iter().any(|p| p.hash == pos.hash) {
+            return 0;
+        }
+        // Cut-offs from the table would leave the principal variation short, so
+        // they are only taken in null windows, which the root never has.
+        let key = pos.key();
+        if let Some(entry) = self.tt.probe(key)
+            && entry.depth as i32 >= depth
+            && !pv_node
+        {
+            let score = from_tt(entry.score, self.ply);
+            let cutoff = match entry.bound {
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+                Bound::Exact => true,
+            };
+            if cutoff {
+                self.table_line(pos, &entry);
+                return score;
+            }
+        }
+        // QSearch stands pat, like in `bound`.
+        if depth == 0 {
+            return pos.score;
+        }
+        // Reverse futility pruning as in `bound`, away from the principal variation.
+        if !pv_node && let Some(score) = self.reverse_futility(pos, beta, depth, can_null, in_check)
+        {
+            return score;
+        }
+        // Null move, on the same conditions as in `bound`. The principal variation
+        // is searched with real moves only.
+        if !pv_node && depth > 2 && can_null && !in_check && pos.score.abs() < 500 {
+            self.play(None);
+            let score = self.child_pvs(&pos.rotate(true), beta - 1, beta, depth - 3, true);
+            if score >= beta && !self.stopped() {
+                return score;
+            }
+        }
+        let (root, searchmoves) = self.allowed_moves(pos, can_null);
+        let allowed = |m: &Move| searchmoves.is_empty() || searchmoves.contains(m);
+        // The hash-move, or failing that one from a more shallow search (IID).
+        let mut killer = self.tt.get_move(key).filter(allowed);
+        if killer.is_none() && depth > 2 {
+            self.pvs(pos, alpha, beta, depth - 3, false);
+            killer = self.tt.get_move(key).filter(allowed);
+        }
+        let val_lower = if in_check {
+            -MATE_UPPER
+        } else {
+            self.qs - depth * self.qs_a
+        };
+        let killer = killer.filter(|m| pos.value(m) >= val_lower);
+        // The killer first, then the moves in the order of `bound`.
+        let moves_vec = pos.gen_moves().into_iter().filter(allowed).collect();
+        let mut moves = self.order_moves(pos, moves_vec, killer, depth, val_lower);
+        if let Some(killer) = killer {
+            moves.insert(0, (pos.value(&killer), killer, MoveKind::Hash));
+        }
+
+        let alpha_orig = alpha;
+        let mut best = -MATE_UPPER;
+        let mut best_move = None;
+        let mut line = Vec::new();
+        // Late quiet moves are reduced, and pruned away from the principal variation.
+        let late = !root && !in_check;
+        for (number, (val, mov, kind)) in moves.into_iter().enumerate() {
+            let quiet = late && kind == MoveKind::Quiet;
+            if quiet && !pv_node && best > self.mated() && self.late_move(depth, number) {
+                continue;
+            }
+            // Futility pruning as in `bound`: the opponent will stand pat, and the
+            // moves after this one are worth even less.
+            let futile = depth <= 1 && !in_check && pos.score + val <= alpha;
+            let (score, move_line) = if futile {
+                if val < MATE_LOWER {
+                    (pos.score + val, vec![mov])
+                } else {
+                    (MATE_UPPER - self.ply - 1, Vec::new())
+                }
+            } else {
+                if root {
+                    self.root_move(mov, number + 1);
+                }
+                let reduction = if quiet {
+                    self.reduction(depth, number)
+                } else {
+                    0
+                };
+                self.pvs_move(pos, mov, alpha, beta, depth, number == 0, reduction)
+            };
+            if self.stopped() {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                line = move_line;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mov);
+            }
+            if alpha >= beta {
+                self.cutoff(pos, mov, kind, number, depth);
+                break;
+            }
+            if futile {
+                break;
+            }
+        }
+        // Mate and stalemate, see `bound`, as is the line of a root that failed low.
+        let mated = self.mated();
+        if best <= mated
+            && let Some(score) = self.no_legal_moves(pos, in_check)
+        {
+            best = score;
+        }
+        if best > mated && (ply > 0 || best > alpha_orig) {
+            self.pv[ply] = line;
+        }
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > alpha_orig {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt
+            .store(key, depth, bound, to_tt(best, self.ply), best_move);
+        best
+    }
+    // Searches a position one ply further from the root, in the window (`alpha`,
+    // `beta`) of the side to move here.
+    fn child_pvs(
+        &mut self,
+        pos: &Position,
+        alpha: i32,
+        beta: i32,
+        depth: i32,
+        can_null: bool,
+    ) -> i32 {
+        self.ply += 1;
+        let score = -self.pvs(pos, -beta, -alpha, depth, can_null);
+        self.ply -= 1;
+        score
+    }
+    // Searches `mov`, returning its score and line. Only the first move gets the
+    // full window, the others just have to prove they are no better, and are
+    // searched again if they are: first to the full depth if they were reduced,
+    // then in the full window.
+    #[allow(clippy::too_many_arguments)]
+    fn pvs_move(
+        &mut self,
+        pos: &Position,
+        mov: Move,
+        alpha: i32,
+        beta: i32,
+        depth: i32,
+        first: bool,
+        reduction: i32,
+    ) -> (i32, Vec<Move>) {
+        let child = pos.domove(mov);
+        self.play(Some(mov));
+        let mut score = if first {
+            self.child_pvs(&child, alpha, beta, depth - 1, true)
+        } else {
+            self.child_pvs(&child, alpha, alpha + 1, depth - 1 - reduction, true)
+        };
+        if reduction > 0 && score > alpha {
+            score = self.child_pvs(&child, alpha, alpha + 1, depth - 1, true);
+        }
+        if !first && score > alpha && score < beta {
+            score = self.child_pvs(&child, alpha, beta, depth - 1, true);
+        }
+        let mut line = Vec::new();
+        if pos.value(&mov) < MATE_LOWER {
+            line.push(mov);
+            line.extend_from_slice(&self.pv[self.ply as usize + 1]);
+        }
+        (score, line)
+    }
+    // Searches the root in a window around the score of the previous iteration,
+    // widening the side the score falls out of until it lands inside.
+    pub(crate) fn aspiration(&mut self, depth: i32) -> Vec<Probe> {
+        let mut ans = Vec::new();
+        let root = self.history[self.history.len() - 1];
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = if depth > 1 && self.score.abs() < MATE_LOWER {
+            (self.score - delta, self.score + delta)
+        } else {
+            (-INF, INF)
+        };
+        loop {
+            let score = self.pvs(&root, alpha, beta, depth, false);
+            if self.stopped() {
+                break;
+            }
+            delta *= 2;
+            if score <= alpha {
+                ans.push(self.probe(&root, depth, Bound::Upper, score));
+                alpha = max(score - delta, -INF);
+            } else if score >= beta {
+                ans.push(self.probe(&root, depth, Bound::Lower, score));
+                beta = min(score + delta, INF);
+            } else {
+                ans.push(self.probe(&root, depth, Bound::Exact, score));
+                self.score = score;
+                break;
+            }
+        }
+        ans
+    }
+}
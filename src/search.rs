@@ -0,0 +1,772 @@
+//! The MTD-bi search: a null-window alpha-beta `bound` driven by a binary
+//! search over the score, with iterative deepening done by the caller.
+//!
+//! The search state and the parts of a node shared with the PVS search in
+//! [`crate::pvs`] live here too.
+use std::cmp::max;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::board::{EMPTY, MATE_LOWER, MATE_UPPER, Move, PAWN, Position, is_opp};
+use crate::ordering::{MoveKind, MoveOrdering};
+use crate::tt::{Bound, TranspositionTable, TtEntry};
+
+// Constants for tuning search, the defaults of the searcher's fields
+pub const QS: i32 = 40;
+pub const QS_A: i32 = 140;
+pub const EVAL_ROUGHNESS: i32 = 15;
+// Pruning and reductions, 0 turns each off
+pub const LMR: i32 = 3; // moves searched in full before later quiet ones are reduced
+pub const LMP: i32 = 4; // quiet moves searched near the leaves, plus depth squared
+pub const RFP: i32 = 120; // margin per ply by which a node may fail high without a search
+
+// Mate scores count plies from the root, the table stores them counted from the position.
+pub(crate) fn to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_LOWER {
+        score + ply
+    } else if score <= -MATE_LOWER {
+        score - ply
+    } else {
+        score
+    }
+}
+pub(crate) fn from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_LOWER {
+        score - ply
+    } else if score <= -MATE_LOWER {
+        score + ply
+    } else {
+        score
+    }
+}
+
+//###############################################################################
+// Search logic
+//###############################################################################
+/// Called by a running search every 1024 nodes and before each root move.
+pub type ProgressHook = Box<dyn FnMut(&Searcher) + Send>;
+
+/// The search run by [`Searcher::search`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Null-window probes in a binary search over the score, see `bound`.
+    #[default]
+    MtdBi,
+    /// Principal variation search with aspiration windows, see `pvs`.
+    Pvs,
+}
+
+/// One search of the root by [`Searcher::search`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Probe {
+    pub depth: i32,
+    pub bound: Bound,
+    pub score: i32,
+    pub mov: Option<Move>, // the best move stored in the table
+    pub pv: Vec<Move>,     // the line of this search, which failed low if empty
+}
+
+/// Search state: the transposition table, the game history, a node counter and
+/// the stop flag used to abort a search from another thread.
+pub struct Searcher {
+    pub tt: TranspositionTable,
+    pub ordering: MoveOrdering,
+    pub history: Vec<Position>,
+    pub nodes: u64, // nodes searched since the caller last reset it
+    pub max_nodes: Option<u64>,
+    pub searchmoves: Vec<Move>, // the root moves to search, all if empty
+    pub stop: Arc<AtomicBool>,
+    // Tuning, see the constants above
+    pub qs: i32,
+    pub qs_a: i32,
+    pub eval_roughness: i32,
+    pub lmr: i32,
+    pub lmp: i32,
+    pub rfp: i32,
+    pub algorithm: Algorithm,
+    // Progress of the running search
+    pub depth: i32,                      // the depth given to `search`
+    pub seldepth: i32,                   // the most plies from the root reached by it
+    pub currmove: Option<(Move, usize)>, // the root move being searched, numbered from 1
+    pub on_progress: Option<ProgressHook>,
+    pub(crate) ply: i32, // the distance of the current node from the root
+    pub(crate) pv: Vec<Vec<Move>>, // the principal variation found below each ply
+    pub(crate) played: Vec<Option<Move>>, // the move made at each ply, None for a null move
+    pub(crate) score: i32, // the last PVS score, the centre of the next window
+    deadline: Option<Instant>, // the hard time limit
+    // While pondering, the flag that is cleared on a ponderhit and the time limit from then.
+    pondering: Option<(Arc<AtomicBool>, Option<Duration>)>,
+    ponderhit: Option<Instant>,
+    timed_out: bool,
+    // We may not have a move yet at depth = 1, so that depth is never aborted.
+    stoppable: bool,
+}
+impl Searcher {
+    pub fn new() -> Searcher {
+        Searcher {
+            tt: TranspositionTable::default(),
+            ordering: MoveOrdering::default(),
+            history: Vec::new(),
+            nodes: 0,
+            max_nodes: None,
+            searchmoves: Vec::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            qs: QS,
+            qs_a: QS_A,
+            eval_roughness: EVAL_ROUGHNESS,
+            lmr: LMR,
+            lmp: LMP,
+            rfp: RFP,
+            algorithm: Algorithm::default(),
+            depth: 0,
+            seldepth: 0,
+            currmove: None,
+            on_progress: None,
+            ply: 0,
+            pv: Vec::new(),
+            played: Vec::new(),
+            score: 0,
+            deadline: None,
+            pondering: None,
+            ponderhit: None,
+            timed_out: false,
+            stoppable: false,
+        }
+    }
+    /// Forgets everything learned in previous games, so a new game is searched
+    /// exactly like by a fresh searcher with the same table size.
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+        self.ordering.clear();
+        self.history.clear();
+        self.nodes = 0;
+        self.max_nodes = None;
+        self.searchmoves.clear();
+        self.score = 0;
+        self.set_deadline(None);
+    }
+    /// Null-window search of `pos` around `gamma` to the given depth.
+    pub fn bound(&mut self, pos: &Position, gamma: i32, mut depth: i32, can_null: bool) -> i32 {
+        // Let s* be the "true" score of the sub-tree we are searching.
+        // The method returns r, where
+        // if gamma >  s* then s* <= r < gamma  (A better upper bound)
+        // if gamma <= s* then gamma <= r <= s* (A better lower bound)
+        if let Some(score) = self.enter(pos) {
+            return score;
+        }
+        let ply = self.ply as usize;
+        // Depth <= 0 is QSearch. Here any position is searched as deeply as is needed for
+        // calmness, and from this point on there is no difference in behaviour depending on
+        // depth, so so there is no reason to keep different depths in the transposition table.
+        depth = max(depth, 0);
+        // Checks are extended, so a position in check is never left to stand pat.
+        let in_check = pos.is_check();
+        depth += self.extension(in_check);
+        // Let's not repeat positions. We don't chat
+        // - at the root (can_null=False) since it is in history, but not a draw.
+        // - at depth=0, since it would be expensive and break "futility pruning".
+        // This comes before the table, whose entries may be from other move orders.
+        if can_null && depth > 0 && self.history.iter().any(|p| p.hash == pos.hash) {
+            return 0;
+        }
+        // Look in the table if we have already searched this position before.
+        // We also need to be sure, that the stored search was at least as deep
+        // as the current search. Entries of previous searches and moves are kept,
+        // but a root limited to some moves can't use the score of all of them.
+        let key = pos.key();
+        if let Some(entry) = self.tt.probe(key)
+            && entry.depth as i32 >= depth
+            && !self.restricted(pos, can_null)
+        {
+            let score = from_tt(entry.score, self.ply);
+            let cutoff = match entry.bound {
+                Bound::Lower => score >= gamma,
+                Bound::Upper => score < gamma,
+                Bound::Exact => true,
+            };
+            if cutoff {
+                self.table_line(pos, &entry);
+                return score;
+            }
+        }
+        // A position far enough above gamma is expected to stay there, so the
+        // moves near the leaves aren't searched. This is reverse futility pruning.
+        if let Some(score) = self.reverse_futility(pos, gamma, depth, can_null, in_check) {
+            return score;
+        }
+        // Call moves
+        let moves: Vec<(Option<Move>, i32, Vec<Move>)> =
+            Self::getmoves(self, depth, can_null, in_check, pos, gamma);
+        // Once stopped, the scores below us are meaningless, so don't store anything.
+        if self.stopped() {
+            return 0;
+        }
+
+        let mut best = -MATE_UPPER;
+        let mut best_move = None;
+        // The line of the cut-off move, or of the best move if none.
+        let mut line = Vec::new();
+        for (mov, score, moves_line) in moves {
+            if score > best {
+                line = moves_line;
+            }
+            best = max(best, score);
+            if best >= gamma {
+                // Save the move for the killer heuristic
+                best_move = mov;
+                break;
+            }
+        }
+        // Stalemate checking is a bit tricky: Say we failed low, because
+        // we can't (legally) move and so the (real) score is -infty.
+        // At the next depth we are allowed to just return r, -infty <= r < gamma,
+        // which is normally fine.
+        // However, what if gamma = -10 and we don't have any legal moves?
+        // Then the score is actaully a draw and we should fail high!
+        // Thus, if best < gamma and best < 0 we need to double check what we are doing.
+
+        // We will fix this problem another way: We add the requirement to bound, that
+        // it always returns a mate score if the king is capturable. Even if another move
+        // was also sufficient to go above gamma. If we see this value we know we are either
+        // mate, or stalemate. It then suffices to check whether we're in check.
+
+        // We know whether we're in check, and the legal moves tell whether there are any.
+        // If there are, they were pruned, and the score is only as good as the pruning.
+        let mated = self.mated();
+        if best <= mated
+            && let Some(score) = self.no_legal_moves(pos, in_check)
+        {
+            best = score;
+        }
+        // A move losing the king isn't legal, so there is no line then. A root that
+        // failed low has no best move, and keeps the line of the last probe empty.
+        if best > mated && (ply > 0 || best >= gamma) {
+            self.pv[ply] = line;
+        }
+        // Table part 2
+        let bound = if best >= gamma {
+            Bound::Lower
+        } else {
+            Bound::Upper
+        };
+        self.tt
+            .store(key, depth, bound, to_tt(best, self.ply), best_move);
+        best
+    }
+    // The start of every node: counts it, looks at the clock and checks for the
+    // king. Returns the score of a node that is over before it began.
+    pub(crate) fn enter(&mut self, pos: &Position) -> Option<i32> {
+        self.nodes += 1;
+        // The line below this node, empty unless a move is found.
+        let ply = self.ply as usize;
+        if self.pv.len() <= ply {
+            self.pv.resize(ply + 1, Vec::new());
+        }
+        self.pv[ply].clear();
+        self.seldepth = max(self.seldepth, self.ply);
+        // Looking at the clock is slow, so only do it now and then.
+        if self.nodes.is_multiple_of(1024) {
+            self.check_ponderhit();
+            if self.deadline.is_some_and(|d| Instant::now() >= d) {
+                self.timed_out = true;
+            }
+            self.report_progress();
+        }
+        // An aborted search unwinds as fast as possible, the caller discards the result.
+        if self.stopped() {
+            return Some(0);
+        }
+        // Sunfish is a king-capture engine, so we should always check if we
+        // still have a king. Notice since this is the only termination check,
+        // the remaining code has to be comfortable with being mated, stalemated
+        //# or able to capture the opponent king.
+        // Mates are scored by their distance from the root, so shorter ones are preferred.
+        if pos.score <= -MATE_LOWER {
+            return Some(-(MATE_UPPER - self.ply));
+        }
+        None
+    }
+    // A node cut off by the table: its line ends with the best move found before.
+    pub(crate) fn table_line(&mut self, pos: &Position, entry: &TtEntry) {
+        if let Some(mov) = entry.mov().filter(|m| pos.value(m) < MATE_LOWER) {
+            self.pv[self.ply as usize].push(mov);
+        }
+    }
+    // The score of a move losing the king right away: being mated two plies from now.
+    pub(crate) fn mated(&self) -> i32 {
+        -(MATE_UPPER - self.ply - 2)
+    }
+    // The score of a position where every move searched loses the king: mated if we
+    // are in check, stalemated otherwise. None if some legal move wasn't searched.
+    pub(crate) fn no_legal_moves(&self, pos: &Position, in_check: bool) -> Option<i32> {
+        if !pos.legal_moves().is_empty() {
+            return None;
+        }
+        Some(if in_check { self.mated() } else { 0 })
+    }
+    // The plies a node is searched deeper. Checks are extended, up to twice the depth
+    // of the search, since a perpetual check would go on forever.
+    pub(crate) fn extension(&self, in_check: bool) -> i32 {
+        i32::from(in_check && self.ply < 2 * self.depth)
+    }
+    // At the root we may be limited to some moves. Only the root and its IID
+    // are searched without null moves, deeper repetitions of it return early.
+    // Returns the moves allowed in `pos`, all if empty.
+    pub(crate) fn allowed_moves(&self, pos: &Position, can_null: bool) -> (bool, Vec<Move>) {
+        let root = !can_null && self.history.last().is_some_and(|p| p.hash == pos.hash);
+        if root && !self.searchmoves.is_empty() {
+            (root, self.searchmoves.clone())
+        } else {
+            (root, Vec::new())
+        }
+    }
+    // The score to fail high with if the static evaluation beats `beta` by a margin
+    // growing with depth. Not at the root, in check or with mate scores around.
+    pub(crate) fn reverse_futility(
+        &self,
+        pos: &Position,
+        beta: i32,
+        depth: i32,
+        can_null: bool,
+        in_check: bool,
+    ) -> Option<i32> {
+        let prune = self.rfp > 0
+            && (1..=3).contains(&depth)
+            && can_null
+            && !in_check
+            && pos.score.abs() < MATE_LOWER
+            && beta.abs() < MATE_LOWER
+            && pos.score - self.rfp * depth >= beta;
+        prune.then_some(pos.score)
+    }
+    // The plies by which the `number`th move of a node, counted from 0, is reduced.
+    pub(crate) fn reduction(&self, depth: i32, number: usize) -> i32 {
+        let lmr = self.lmr as usize;
+        if lmr == 0 || depth < 3 || number < lmr {
+            0
+        } else if depth >= 6 && number >= 3 * lmr {
+            2
+        } else {
+            1
+        }
+    }
+    // Whether the `number`th move of a node near the leaves is too late to search.
+    pub(crate) fn late_move(&self, depth: i32, number: usize) -> bool {
+        self.lmp > 0 && depth <= 3 && number as i32 >= self.lmp + depth * depth
+    }
+    // Notes the move made at this ply, before the position after it is searched.
+    pub(crate) fn play(&mut self, mov: Option<Move>) {
+        let ply = self.ply as usize;
+        if self.played.len() <= ply {
+            self.played.resize(ply + 1, None);
+        }
+        self.played[ply] = mov;
+    }
+    // The opponent's move leading here, None at the root or after a null move.
+    pub(crate) fn prev_move(&self) -> Option<Move> {
+        let ply = self.ply as usize;
+        if ply == 0 { None } else { self.played[ply - 1] }
+    }
+    // The moves worth searching at `depth` other than the hash move, in the order to
+    // search them. Near the leaves that is by value, as futility pruning needs.
+    pub(crate) fn order_moves(
+        &self,
+        pos: &Position,
+        moves: Vec<Move>,
+        hash: Option<Move>,
+        depth: i32,
+        val_lower: i32,
+    ) -> Vec<(i32, Move, MoveKind)> {
+        let prev = self.prev_move();
+        let mut moves: Vec<(i32, Move, MoveKind)> = moves
+            .into_iter()
+            .filter(|m| Some(*m) != hash)
+            .map(|m| (pos.value(&m), m))
+            .filter(|&(val, _)| val >= val_lower)
+            .map(|(val, m)| (val, m, self.ordering.kind(pos, &m, hash, self.ply, prev)))
+            .collect();
+        moves.sort_by_key(|(val, _, _)| -val);
+        if depth > 1 {
+            self.ordering.sort(&mut moves, self.ply);
+        }
+        moves
+    }
+    // Takes note of `mov` reaching the bound after `number` other moves.
+    pub(crate) fn cutoff(
+        &mut self,
+        pos: &Position,
+        mov: Move,
+        kind: MoveKind,
+        number: usize,
+        depth: i32,
+    ) {
+        self.ordering.count(kind, number);
+        if depth > 0 && quiet(pos, &mov) {
+            self.ordering.learn(mov, self.ply, depth, self.prev_move());
+        }
+    }
+    // Whether `pos` is a root limited to some moves.
+    pub(crate) fn restricted(&self, pos: &Position, can_null: bool) -> bool {
+        !self.searchmoves.is_empty()
+            && !can_null
+            && self.history.last().is_some_and(|p| p.hash == pos.hash)
+    }
+    // Lets the owner of the searcher know how far it has got.
+    fn report_progress(&mut self) {
+        if let Some(mut on_progress) = self.on_progress.take() {
+            on_progress(self);
+            self.on_progress = Some(on_progress);
+        }
+    }
+    // Takes note of the root move about to be searched.
+    pub(crate) fn root_move(&mut self, mov: Move, number: usize) {
+        self.currmove = Some((mov, number));
+        self.report_progress();
+    }
+    // Searches a position one ply further from the root.
+    fn child(&mut self, pos: &Position, gamma: i32, depth: i32, can_null: bool) -> i32 {
+        self.ply += 1;
+        let score = self.bound(pos, gamma, depth, can_null);
+        self.ply -= 1;
+        score
+    }
+    // Searches `mov`, returning its score and line: the move followed by the line
+    // below it. King captures end the game, so they aren't part of any line.
+    // A reduced move that fails high is searched again to the full depth.
+    fn search_move(
+        &mut self,
+        pos: &Position,
+        mov: Move,
+        gamma: i32,
+        depth: i32,
+        reduction: i32,
+    ) -> (Option<Move>, i32, Vec<Move>) {
+        let child = pos.domove(mov);
+        self.play(Some(mov));
+        let mut score = -self.child(&child, 1 - gamma, depth - 1 - reduction, true);
+        if reduction > 0 && score >= gamma {
+            score = -self.child(&child, 1 - gamma, depth - 1, true);
+        }
+        let mut line = Vec::new();
+        if pos.value(&mov) < MATE_LOWER {
+            line.push(mov);
+            line.extend_from_slice(&self.pv[self.ply as usize + 1]);
+        }
+        (Some(mov), score, line)
+    }
+    // Generator of moves to search in order.
+    // This allows us to define the moves, but only calculate them if needed.
+    fn getmoves(
+        &mut self,
+        depth: i32,
+        can_null: bool,
+        in_check: bool,
+        pos: &Position,
+        gamma: i32,
+    ) -> Vec<(Option<Move>, i32, Vec<Move>)> {
+        let mut ans: Vec<(Option<Move>, i32, Vec<Move>)> = Vec::new();
+        // First try not moving at all. We only do this if there is at least one major
+        // piece left on the board, since otherwise zugzwangs are too dangerous.
+        // FIXME: We also can't null move if we can capture the opponent king.
+        // Since if we do, we won't spot illegal moves that could lead to stalemate.
+        // For now we just solve this by not using null-move in very unbalanced positions.
+        // TODO: We could actually use null-move in QS as well. Not sure it would be very useful.
+        // But still.... We just have to move stand-pat to be before null-move.
+        //if depth > 2 and can_null and any(c in pos.board for c in "RBNQ"):
+        //if depth > 2 and can_null and any(c in pos.board for c in "RBNQ") and abs(pos.score) < 500:
+        // Never in check, where not moving just loses the king.
+        if depth > 2 && can_null && !in_check && pos.score.abs() < 500 {
+            self.play(None);
+            ans.push((
+                None,
+                -self.child(&pos.rotate(true), 1 - gamma, depth - 3, true),
+                Vec::new(),
+            ));
+            if ans[0].1 >= gamma {
+                return ans;
+            }
+        }
+        // For QSearch we have a different kind of null-move, namely we can just stop
+        // and not capture anything else.
+        if depth == 0 {
+            ans.push((None, pos.score, Vec::new()));
+            return ans;
+        }
+        let (root, searchmoves) = self.allowed_moves(pos, can_null);
+        let allowed = |m: &Move| searchmoves.is_empty() || searchmoves.contains(m);
+        // Look for the strongest ove from last time, the hash-move.
+        let mut killer = self.tt.get_move(pos.key()).filter(allowed);
+        // If there isn't one, try to find one with a more shallow search.
+        // This is known as Internal Iterative Deepening (IID). We set
+        // can_null=True, since we want to make sure we actually find a move.
+        if killer.is_none() && depth > 2 {
+            self.bound(pos, gamma, depth - 3, false);
+            killer = self.tt.get_move(pos.key()).filter(allowed);
+        }
+        // If depth == 0 we only try moves with high intrinsic score (captures and
+        // promotions). Otherwise we do all moves. This is called quiescent search.
+        // In check, every move is searched, since most of them lose the king.
+        let val_lower = if in_check {
+            -MATE_UPPER
+        } else {
+            self.qs - depth * self.qs_a
+        };
+        // Only play the move if it would be included at the current val-limit,
+        // since otherwise we'd get search instability.
+        // It is left out of the main loop below.
+        let killer = killer.filter(|m| pos.value(m) >= val_lower);
+        let mut number = 0;
+        if let Some(killer_move) = killer {
+            if root {
+                number += 1;
+                self.root_move(killer_move, number);
+            }
+            ans.push(self.search_move(pos, killer_move, gamma, depth, 0));
+            if ans[ans.len() - 1].1 >= gamma {
+                self.cutoff(pos, killer_move, MoveKind::Hash, 0, depth);
+                return ans;
+            }
+        }
+        // Late quiet moves are reduced or pruned, except at the root and in check.
+        // Moves are only pruned once one of them is legal, so a pruned node never
+        // looks like mate or stalemate.
+        let late = !root && !in_check;
+        let mut searched = usize::from(killer.is_some());
+        let mut legal = ans
+            .last()
+            .is_some_and(|(m, s, _)| m.is_some() && *s > self.mated());
+        let mut moves_vec = pos.gen_moves();
+        moves_vec.retain(allowed);
+        // Captures first, then the quiet moves that caused cut-offs before.
+        let ms1 = self.order_moves(pos, moves_vec, killer, depth, val_lower);
+        for (val, mov, kind) in ms1 {
+            // If the new score is less than gamma, the opponent will for sure just
+            // stand pat, since ""pos.score + val < gamma === -(pos.score + val) >= 1-gamma""
+            // This is known as futility pruning.
+            // Not in check, where the opponent doesn't stand pat but takes the king.
+            if depth <= 1 && !in_check && pos.score + val < gamma {
+                // Need special case for MATE, since it would normally be caught
+                // before standing pat.
+                let scr = if val < MATE_LOWER {
+                    pos.score + val
+                } else {
+                    MATE_UPPER - self.ply - 1
+                };
+                let line = if val < MATE_LOWER {
+                    vec![mov]
+                } else {
+                    Vec::new()
+                };
+                ans.push((Some(mov), scr, line));
+                // We can also break, since we have ordered the moves by value,
+                // so it can't get any better than this.
+                break;
+            }
+            // Killers and countermoves are as good as quiet moves get.
+            let quiet = late && kind == MoveKind::Quiet;
+            if quiet && legal && self.late_move(depth, searched) {
+                continue;
+            }
+            if root {
+                number += 1;
+                self.root_move(mov, number);
+            }
+            let reduction = if quiet {
+                self.reduction(depth, searched)
+            } else {
+                0
+            };
+            let result = self.search_move(pos, mov, gamma, depth, reduction);
+            legal |= result.1 > self.mated();
+            // Like the null move and the killer, a move reaching gamma is the last one
+            // the caller looks at. Checks are extended, which we couldn't afford otherwise.
+            let cutoff = result.1 >= gamma;
+            ans.push(result);
+            if cutoff {
+                self.cutoff(pos, mov, kind, searched, depth);
+                break;
+            }
+            searched += 1;
+        }
+        ans
+    }
+    /// The principal variation of the last search of the root, e.g. by `bound`
+    /// or a probe of `search`. Empty if that search failed low.
+    pub fn pv(&self) -> &[Move] {
+        self.pv.first().map_or(&[], |line| line)
+    }
+    /// Whether the search has been asked to stop, or has used up its nodes or time,
+    /// and may be aborted.
+    pub fn stopped(&self) -> bool {
+        self.stoppable
+            && (self.stop.load(Ordering::Relaxed)
+                || self.timed_out
+                || self.max_nodes.is_some_and(|n| self.nodes >= n))
+    }
+    // Puts the searcher back in order after a search panicked halfway.
+    pub(crate) fn recover(&mut self) {
+        self.ply = 0;
+        self.on_progress = None;
+        self.max_nodes = None;
+        self.searchmoves.clear();
+        self.set_deadline(None);
+    }
+    /// Sets the time at which searches are aborted, `None` for no limit.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+        self.timed_out = false;
+        self.pondering = None;
+        self.ponderhit = None;
+    }
+    /// Searches without a time limit while `pondering` is set. Once it is cleared,
+    /// the search is aborted `hard` after that moment, if given.
+    pub fn set_pondering(&mut self, pondering: Arc<AtomicBool>, hard: Option<Duration>) {
+        self.set_deadline(None);
+        self.pondering = Some((pondering, hard));
+    }
+    /// When the search noticed that pondering ended, if it did.
+    pub fn ponderhit(&self) -> Option<Instant> {
+        self.ponderhit
+    }
+    // Starts the clock as soon as pondering ends, not only after the iteration.
+    fn check_ponderhit(&mut self) {
+        if let Some((pondering, hard)) = &self.pondering
+            && !pondering.load(Ordering::Relaxed)
+        {
+            let now = Instant::now();
+            self.deadline = hard.map(|hard| now + hard);
+            self.ponderhit = Some(now);
+            self.pondering = None;
+        }
+    }
+    /// Searches the last position of `history` to a single depth with the chosen
+    /// algorithm, returning a [`Probe`] for every search of the root.
+    /// A stopped search returns the root searches completed so far.
+    ///
+    /// The table is shared by all depths and moves. Call `tt.new_search` once per
+    /// move, so the entries of older moves are the first to be replaced.
+    pub fn search(&mut self, history: Vec<Position>, depth: i32) -> Vec<Probe> {
+        self.start(history, depth);
+        match self.algorithm {
+            Algorithm::MtdBi => self.mtd_bi(depth),
+            Algorithm::Pvs => self.aspiration(depth),
+        }
+    }
+    /// Probes the last position of `history` to a single depth with null windows
+    /// at 0 and 1: it is a draw if the first fails high and the second fails low.
+    /// A stopped search returns the probes completed so far.
+    pub fn draw_probes(&mut self, history: Vec<Position>, depth: i32) -> Vec<Probe> {
+        self.start(history, depth);
+        let root = self.history[self.history.len() - 1];
+        let mut ans = Vec::new();
+        for gamma in [0, 1] {
+            let score = self.bound(&root, gamma, depth, false);
+            if self.stopped() {
+                break;
+            }
+            let bound = if score >= gamma {
+                Bound::Lower
+            } else {
+                Bound::Upper
+            };
+            ans.push(self.probe(&root, depth, bound, score));
+        }
+        ans
+    }
+    // Sets up a search of the last position of `history` to `depth`.
+    fn start(&mut self, history: Vec<Position>, depth: i32) {
+        self.history = history;
+        self.stoppable = depth > 1;
+        self.depth = depth;
+        self.seldepth = 0;
+        self.currmove = None;
+    }
+    // The report of a finished search of the root. Only a search that failed high
+    // or is exact has a line, which starts with the move stored in the table.
+    pub(crate) fn probe(&self, root: &Position, depth: i32, bound: Bound, score: i32) -> Probe {
+        let mov = self.tt.get_move(root.key());
+        let pv = match mov {
+            _ if bound == Bound::Upper => Vec::new(),
+            Some(m) if self.pv().first() == Some(&m) => self.pv().to_vec(),
+            Some(m) => vec![m],
+            None => Vec::new(),
+        };
+        let pv = self.complete_line(root, pv, depth);
+        Probe {
+            depth,
+            bound,
+            score,
+            mov,
+            pv,
+        }
+    }
+    // A line ends early where the table cut the search off. It is continued with the
+    // best moves in the table, until it is `depth` moves long or a position repeats.
+    fn complete_line(&self, root: &Position, mut line: Vec<Move>, depth: i32) -> Vec<Move> {
+        if line.is_empty() {
+            return line;
+        }
+        let mut pos = *root;
+        let mut seen = vec![pos.hash];
+        for &mov in &line {
+            pos = pos.domove(mov);
+            seen.push(pos.hash);
+        }
+        while (line.len() as i32) < depth {
+            let Some(mov) = self.tt.get_move(pos.key()) else {
+                break;
+            };
+            if !pos.legal_moves().contains(&mov) {
+                break;
+            }
+            pos = pos.domove(mov);
+            if seen.contains(&pos.hash) {
+                break;
+            }
+            seen.push(pos.hash);
+            line.push(mov);
+        }
+        line
+    }
+    // Iterative deepening MTD-bi search, one null-window probe per root search.
+    fn mtd_bi(&mut self, depth: i32) -> Vec<Probe> {
+        let mut ans = Vec::new();
+        let root = self.history[self.history.len() - 1];
+        let mut gamma = 0;
+        // The inner loop is a binary search on the score of the position.
+        // Inv: lower <= score <= upper
+        // 'while lower != upper' would work, but it's too much effort to spend
+        // on what's probably not going to change the move played.
+        // Once the score is known to be a mate it is pinned down exactly, so the
+        // reported mate distance is right.
+        let (mut lower, mut upper) = (-MATE_UPPER, MATE_UPPER);
+        let is_mate = |lower: i32, upper: i32| lower >= MATE_LOWER || upper <= -MATE_LOWER;
+        while lower < upper - self.eval_roughness || (is_mate(lower, upper) && lower < upper) {
+            let score = self.bound(&root, gamma, depth, false);
+            if self.stopped() {
+                break;
+            }
+            let bound = if score >= gamma {
+                lower = score;
+                Bound::Lower
+            } else {
+                upper = score;
+                Bound::Upper
+            };
+            ans.push(self.probe(&root, depth, bound, score));
+            gamma = (lower + upper + 1) / 2;
+        }
+        ans
+    }
+}
+// Whether `mov` neither captures nor promotes.
+pub(crate) fn quiet(pos: &Position, mov: &Move) -> bool {
+    let en_passant = pos.board[mov.i] == PAWN && mov.j == pos.ep;
+    !is_opp(pos.board[mov.j]) && mov.prom == EMPTY && !en_passant
+}
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
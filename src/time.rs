@@ -0,0 +1,187 @@
+//! Time management: how long to think about a move.
+//!
+//! The [`TimeManager`] has two limits. The soft limit is checked between
+//! iterations: once it has passed, starting another iteration isn't worth it.
+//! The hard limit aborts the running iteration from inside the search. The soft
+//! limit grows while the search is unstable, i.e. the best move changes or the
+//! score drops, but never beyond the hard limit.
+use std::time::{Duration, Instant};
+
+// Moves left in the game when the GUI doesn't say.
+const DEFAULT_MOVESTOGO: i32 = 40;
+// How far the soft limit may be extended by an unstable search.
+const MAX_SCALE: f64 = 3.0;
+// A score drop, in centipawns, that makes us think longer.
+const SCORE_DROP: i32 = 30;
+
+/// The remaining time on our clock, in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Clock {
+    pub time: i32,
+    pub inc: i32,
+    pub movestogo: Option<i32>,
+}
+
+pub struct TimeManager {
+    start: Instant,
+    soft: Option<Duration>,
+    hard: Option<Duration>,
+    scale: f64, // the extension of the soft limit
+}
+impl TimeManager {
+    /// Limits for a search with a fixed `movetime` and/or a `clock`, both in
+    /// milliseconds, keeping `overhead` milliseconds for communication lag.
+    /// Without either the search has no time limit.
+    pub fn new(movetime: Option<i32>, clock: Option<Clock>, overhead: i32) -> TimeManager {
+        // The GUI may send anything, so compute in i64, where no i32 input can overflow.
+        let ms = |t: i64| Duration::from_millis(t.max(1) as u64);
+        let min = |a: Option<Duration>, b| Some(a.map_or(b, |a: Duration| a.min(b)));
+        let overhead = overhead as i64;
+        let (mut soft, mut hard) = (None, None);
+        if let Some(movetime) = movetime {
+            let movetime = movetime as i64 - overhead;
+            // Don't start an iteration we can't expect to finish.
+            soft = Some(ms(movetime * 2 / 3));
+            hard = Some(ms(movetime));
+        }
+        if let Some(Clock {
+            time,
+            inc,
+            movestogo,
+        }) = clock
+        {
+            let left = time as i64 - overhead;
+            let movestogo = movestogo.unwrap_or(DEFAULT_MOVESTOGO).max(1) as i64;
+            let target = (left / movestogo + inc as i64).min(left / 2);
+            soft = min(soft, ms(target * 2 / 3));
+            // An extended search may take some from later moves, but never most of the clock.
+            hard = min(hard, ms((target * 3).min(left / 2)));
+        }
+        TimeManager {
+            start: Instant::now(),
+            soft,
+            hard,
+            scale: 1.0,
+        }
+    }
+    /// Restarts the clock at `start`, e.g. at a ponderhit.
+    pub fn restart(&mut self, start: Instant) {
+        self.start = start;
+    }
+    /// Time since the search (or the clock) started.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+    /// How long an iteration may run from the start, if limited.
+    pub fn hard_limit(&self) -> Option<Duration> {
+        self.hard
+    }
+    /// When the running iteration must be aborted, if ever.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.hard.map(|hard| self.start + hard)
+    }
+    /// Whether it isn't worth starting another iteration.
+    pub fn soft_exceeded(&self) -> bool {
+        let Some(soft) = self.soft else {
+            return false;
+        };
+        let soft = soft.mul_f64(self.scale);
+        self.elapsed() > self.hard.map_or(soft, |hard| soft.min(hard))
+    }
+    /// Takes note of a finished iteration, extending the soft limit if the best
+    /// move changed or the score dropped compared to the previous iteration.
+    pub fn update(&mut self, best_changed: bool, score: i32, prev_score: i32) {
+        if best_changed {
+            self.scale = (self.scale * 1.5).min(MAX_SCALE);
+        }
+        if score < prev_score - SCORE_DROP {
+            self.scale = (self.scale * 1.25).min(MAX_SCALE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(time: i32, inc: i32, movestogo: Option<i32>) -> Option<Clock> {
+        Some(Clock {
+            time,
+            inc,
+            movestogo,
+        })
+    }
+    // The soft and hard limit in milliseconds.
+    fn limits(tm: &TimeManager) -> (Option<u128>, Option<u128>) {
+        (
+            tm.soft.map(|d| d.as_millis()),
+            tm.hard.map(|d| d.as_millis()),
+        )
+    }
+
+    #[test]
+    fn limits_from_the_go_parameters() {
+        #[rustfmt::skip]
+        let cases = [
+            // movetime, clock, overhead => soft, hard
+            (None, None, 10, None, None),
+            (Some(3000), None, 10, Some(1993), Some(2990)),
+            (Some(3000), None, 0, Some(2000), Some(3000)),
+            // A fortieth of the clock without movestogo, three times that at most.
+            (None, clock(60000, 0, None), 10, Some(999), Some(4497)),
+            (None, clock(60000, 1000, Some(10)), 0, Some(4666), Some(21000)),
+            // Never more than half the clock, however large the increment.
+            (None, clock(10000, 8000, None), 0, Some(3333), Some(5000)),
+            (None, clock(1000, 0, None), 10, Some(16), Some(72)),
+            // Both: the tighter limit wins.
+            (Some(500), clock(60000, 0, None), 0, Some(333), Some(500)),
+            // Out of time, or less than the overhead: still a millisecond.
+            (None, clock(1, 0, None), 10, Some(1), Some(1)),
+            (None, clock(1, 5000, None), 0, Some(1), Some(1)),
+            (Some(5), None, 10, Some(1), Some(1)),
+            // Whatever the GUI sends mustn't overflow.
+            (Some(i32::MAX), None, 10, Some(1431655758), Some(2147483637)),
+            (None, clock(1000, i32::MAX, None), 0, Some(333), Some(500)),
+            (None, clock(i32::MAX, i32::MAX, Some(0)), 0, Some(715827882), Some(1073741823)),
+            (None, clock(i32::MAX, 0, None), i32::MIN, Some(71582788), Some(322122546)),
+        ];
+        for (movetime, clock, overhead, soft, hard) in cases {
+            let tm = TimeManager::new(movetime, clock, overhead);
+            assert_eq!(limits(&tm), (soft, hard), "{:?} {:?}", movetime, clock);
+        }
+    }
+
+    #[test]
+    fn unstable_searches_get_more_time() {
+        let mut tm = TimeManager::new(Some(3000), None, 0);
+        tm.update(true, 0, 0);
+        assert_eq!(tm.scale, 1.5);
+        tm.update(false, -31, 0);
+        assert_eq!(tm.scale, 1.875);
+        // A small drop doesn't count.
+        tm.update(false, -30, 0);
+        assert_eq!(tm.scale, 1.875);
+        for _ in 0..10 {
+            tm.update(true, -100, 0);
+        }
+        assert_eq!(tm.scale, MAX_SCALE);
+    }
+
+    #[test]
+    fn soft_limit_stays_below_the_hard_limit() {
+        // Soft 2000 and hard 3000 milliseconds, with 2500 gone.
+        let mut tm = TimeManager::new(Some(3000), None, 0);
+        tm.start = Instant::now() - Duration::from_millis(2500);
+        assert!(tm.soft_exceeded());
+        tm.update(true, 0, 0);
+        assert!(!tm.soft_exceeded());
+        for _ in 0..10 {
+            tm.update(true, 0, 0);
+        }
+        tm.start = Instant::now() - Duration::from_millis(3100);
+        assert!(tm.soft_exceeded());
+        assert!(tm.deadline().unwrap() < Instant::now());
+        // Without limits it is never time to stop.
+        assert!(!TimeManager::new(None, None, 10).soft_exceeded());
+    }
+}
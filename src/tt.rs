@@ -0,0 +1,254 @@
+//! A fixed-size transposition table, replacing the unbounded `tp_score` and
+//! `tp_move` maps of the original sunfish.
+//!
+//! Entries are grouped in buckets of [`BUCKET_SIZE`]. A position is stored in the
+//! bucket selected by its key, replacing its own entry, an empty one, or the
+//! least valuable one, where entries from older searches count as less valuable.
+use std::mem::size_of;
+
+use crate::board::Move;
+
+pub const BUCKET_SIZE: usize = 4;
+pub const DEFAULT_HASH_MB: usize = 16;
+
+/// How the stored score relates to the true score of the position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Bound {
+    #[default]
+    Lower, // true score >= score, the search failed high
+    Upper, // true score <= score, the search failed low
+    Exact,
+}
+
+/// One transposition table slot. A key of 0 marks an empty slot.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TtEntry {
+    key: u64,
+    pub score: i32,
+    pub depth: i16,
+    pub bound: Bound,
+    age: u8,
+    // The best move, packed as from/to squares and promotion, from = 0 if none.
+    from: u8,
+    to: u8,
+    prom: u8,
+}
+impl TtEntry {
+    /// The best move found for the position, if any.
+    pub fn mov(&self) -> Option<Move> {
+        if self.from == 0 {
+            return None;
+        }
+        Some(Move {
+            i: self.from as usize,
+            j: self.to as usize,
+            prom: self.prom,
+        })
+    }
+}
+
+type Bucket = [TtEntry; BUCKET_SIZE];
+
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    age: u8,
+}
+impl TranspositionTable {
+    /// A table using about `mb` megabytes.
+    pub fn new(mb: usize) -> TranspositionTable {
+        let count = (mb.max(1) << 20) / size_of::<Bucket>();
+        TranspositionTable {
+            buckets: vec![[TtEntry::default(); BUCKET_SIZE]; count],
+            age: 0,
+        }
+    }
+    /// Reallocates the table to about `mb` megabytes, dropping its contents.
+    pub fn resize(&mut self, mb: usize) {
+        *self = TranspositionTable::new(mb);
+    }
+    /// Empties the table.
+    pub fn clear(&mut self) {
+        self.buckets.fill([TtEntry::default(); BUCKET_SIZE]);
+        self.age = 0;
+    }
+    /// Starts the search of a new move. Entries of older searches stay usable,
+    /// but are replaced first.
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+    fn bucket(&self, key: u64) -> usize {
+        ((key as u128 * self.buckets.len() as u128) >> 64) as usize
+    }
+    /// Looks up the entry of the position with the given key.
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let key = key.max(1);
+        self.buckets[self.bucket(key)]
+            .iter()
+            .find(|e| e.key == key)
+            .copied()
+    }
+    /// The best move stored for the position with the given key.
+    pub fn get_move(&self, key: u64) -> Option<Move> {
+        self.probe(key).and_then(|e| e.mov())
+    }
+    /// Stores a search result. Without a new move, a previously stored move of the
+    /// same position is kept.
+    pub fn store(&mut self, key: u64, depth: i32, bound: Bound, score: i32, mov: Option<Move>) {
+        let key = key.max(1);
+        let age = self.age;
+        let index = self.bucket(key);
+        let bucket = &mut self.buckets[index];
+        let slot = match bucket.iter().position(|e| e.key == key) {
+            Some(slot) => slot,
+            None => {
+                // Prefer empty slots, then old and shallow entries.
+                let worth = |e: &TtEntry| {
+                    if e.key == 0 {
+                        i32::MIN
+                    } else {
+                        e.depth as i32 - 8 * age.wrapping_sub(e.age) as i32
+                    }
+                };
+                (0..BUCKET_SIZE).min_by_key(|&s| worth(&bucket[s])).unwrap()
+            }
+        };
+        let old = bucket[slot];
+        let (from, to, prom) = match mov {
+            Some(m) => (m.i as u8, m.j as u8, m.prom),
+            None if old.key == key => (old.from, old.to, old.prom),
+            None => (0, 0, 0),
+        };
+        bucket[slot] = TtEntry {
+            key,
+            score,
+            depth: depth as i16,
+            bound,
+            age,
+            from,
+            to,
+            prom,
+        };
+    }
+    /// Permille of the table used by the current search, estimated from its start.
+    pub fn hashfull(&self) -> usize {
+        let sample = self.buckets.iter().take(1000 / BUCKET_SIZE);
+        sample
+            .flatten()
+            .filter(|e| e.key != 0 && e.age == self.age)
+            .count()
+            * 1000
+            / (self.buckets.len().min(1000 / BUCKET_SIZE) * BUCKET_SIZE)
+    }
+}
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Position;
+
+    // Keys this small all fall in the first bucket.
+    const KEYS: [u64; BUCKET_SIZE + 1] = [1, 2, 3, 4, 5];
+
+    fn moves() -> Vec<Move> {
+        Position::initial().gen_moves()
+    }
+
+    #[test]
+    fn store_and_probe() {
+        let mut tt = TranspositionTable::new(1);
+        let mov = moves()[0];
+        assert!(tt.probe(KEYS[0]).is_none());
+        tt.store(KEYS[0], 3, Bound::Exact, 42, Some(mov));
+        let entry = tt.probe(KEYS[0]).unwrap();
+        assert_eq!(
+            (entry.depth, entry.bound, entry.score),
+            (3, Bound::Exact, 42)
+        );
+        assert_eq!(tt.get_move(KEYS[0]), Some(mov));
+        // A position replaces its own entry, even with a shallower search.
+        tt.store(KEYS[0], 1, Bound::Upper, -7, None);
+        let entry = tt.probe(KEYS[0]).unwrap();
+        assert_eq!(
+            (entry.depth, entry.bound, entry.score),
+            (1, Bound::Upper, -7)
+        );
+    }
+
+    #[test]
+    fn store_without_move_keeps_the_move() {
+        let mut tt = TranspositionTable::new(1);
+        let moves = moves();
+        tt.store(KEYS[0], 2, Bound::Lower, 10, Some(moves[0]));
+        tt.store(KEYS[0], 4, Bound::Upper, 5, None);
+        assert_eq!(tt.get_move(KEYS[0]), Some(moves[0]));
+        tt.store(KEYS[0], 5, Bound::Lower, 20, Some(moves[1]));
+        assert_eq!(tt.get_move(KEYS[0]), Some(moves[1]));
+        // Another position doesn't inherit it.
+        tt.store(KEYS[1], 5, Bound::Lower, 20, None);
+        assert_eq!(tt.get_move(KEYS[1]), None);
+    }
+
+    #[test]
+    fn full_bucket_replaces_the_shallowest() {
+        let mut tt = TranspositionTable::new(1);
+        for (key, depth) in KEYS.iter().zip([5, 1, 7, 3]) {
+            tt.store(*key, depth, Bound::Exact, 0, None);
+        }
+        tt.store(KEYS[4], 2, Bound::Exact, 0, None);
+        let kept: Vec<bool> = KEYS.iter().map(|&k| tt.probe(k).is_some()).collect();
+        assert_eq!(kept, [true, false, true, true, true]);
+    }
+
+    #[test]
+    fn older_searches_are_replaced_first() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(KEYS[0], 9, Bound::Exact, 0, None);
+        tt.new_search();
+        for (key, depth) in KEYS[1..4].iter().zip([1, 2, 3]) {
+            tt.store(*key, depth, Bound::Exact, 0, None);
+        }
+        // The deepest entry goes, having been stored by an earlier search.
+        tt.store(KEYS[4], 1, Bound::Exact, 0, None);
+        let kept: Vec<bool> = KEYS.iter().map(|&k| tt.probe(k).is_some()).collect();
+        assert_eq!(kept, [false, true, true, true, true]);
+    }
+
+    #[test]
+    fn entries_outlive_their_search() {
+        let mut tt = TranspositionTable::new(1);
+        let mov = moves()[0];
+        tt.store(KEYS[0], 6, Bound::Exact, 15, Some(mov));
+        tt.new_search();
+        tt.new_search();
+        let entry = tt.probe(KEYS[0]).unwrap();
+        assert_eq!((entry.depth, entry.score, entry.mov()), (6, 15, Some(mov)));
+        // Storing it again makes it current, keeping the move.
+        tt.store(KEYS[0], 7, Bound::Lower, 20, None);
+        assert_eq!(tt.get_move(KEYS[0]), Some(mov));
+        assert_eq!(tt.hashfull(), 1);
+    }
+
+    #[test]
+    fn hashfull() {
+        let mut tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        // The estimate samples the first 1000 slots.
+        for key in &KEYS[..4] {
+            tt.store(*key, 1, Bound::Exact, 0, None);
+        }
+        assert_eq!(tt.hashfull(), 4);
+        // Only the current search counts.
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+        tt.store(KEYS[0], 1, Bound::Exact, 0, None);
+        assert_eq!(tt.hashfull(), 1);
+        tt.clear();
+        assert_eq!(tt.hashfull(), 0);
+        assert!(tt.probe(KEYS[0]).is_none());
+    }
+}
@@ -0,0 +1,515 @@
+//! The UCI front end: move (de)serialisation, the go/mate/perft loops and the
+//! command loop run by the binary.
+use crate::bitboard::{self, BitPosition};
+use crate::board::{EMPTY, Move, Position, from_char, from_fen, get_color, parse, to_char};
+use crate::command::UciCommand;
+use crate::engine::{Engine, InfoKind, Limits, format_score};
+use crate::options::{OptionKind, OptionValue, Options};
+use crate::search::{Algorithm, Searcher};
+use crate::search::{EVAL_ROUGHNESS, LMP, LMR, QS, QS_A, RFP};
+use crate::time::Clock;
+use crate::tt::{Bound, DEFAULT_HASH_MB};
+
+pub const VERSION: &str = "sunfish 2023";
+
+//###############################################################################
+// UCI User interface
+//###############################################################################
+fn chr(val: usize) -> String {
+    //let chrs = b'a'..=b'h';
+    //if val < 1 || val > 8 {
+    //    return " ".to_string(); // Invalid input
+    //}
+    //(chrs.clone().nth(val - 1).unwrap() as char).to_string()
+    let i: u8 = 96 + val as u8;
+    (i as char).to_string()
+}
+/// Renders a board index as an algebraic square from white's side, e.g. `e4`.
+pub fn render(i: usize) -> String {
+    let h1: usize = 98;
+    let rank = (h1 - i) / 10;
+    let fil = i % 10;
+    chr(fil) + &((rank + 1).to_string())
+}
+/// Renders a move in UCI notation. `white_pov` tells whether the move is
+/// given from white's point of view, otherwise the squares are flipped.
+pub fn render_move(mov: Option<Move>, white_pov: bool) -> String {
+    let Some(mov) = mov else {
+        return "(none)".to_string();
+    };
+    let (mut i, mut j) = (mov.i, mov.j);
+    if !white_pov {
+        (i, j) = (119 - i, 119 - j);
+    }
+    let prom = if mov.prom == EMPTY {
+        String::new()
+    } else {
+        to_char(mov.prom).to_ascii_lowercase().to_string()
+    };
+    render(i) + &render(j) + &prom
+}
+/// Parses a UCI move such as `e2e4` or `e7e8q` and checks that it is legal in `pos`.
+/// Returns a description of the problem for malformed or illegal moves.
+pub fn parse_move(move_str: &str, pos: &Position) -> Result<Move, String> {
+    let chars: Vec<char> = move_str.chars().collect();
+    let is_square = |c: &[char]| ('a'..='h').contains(&c[0]) && ('1'..='8').contains(&c[1]);
+    if !(4..=5).contains(&chars.len())
+        || !is_square(&chars[0..2])
+        || !is_square(&chars[2..4])
+        || chars.len() == 5 && !"nbrq".contains(chars[4])
+    {
+        return Err(format!("malformed move {}", move_str));
+    }
+    let mut i = parse([chars[0], chars[1]]);
+    let mut j = parse([chars[2], chars[3]]);
+    let prom = if chars.len() > 4 {
+        from_char(chars[4].to_ascii_uppercase())
+    } else {
+        EMPTY
+    };
+    if get_color(pos) == 1 {
+        (i, j) = (119 - i, 119 - j);
+    }
+    let mov = Move {
+        i: i as usize,
+        j: j as usize,
+        prom,
+    };
+    if !pos.legal_moves().contains(&mov) {
+        return Err(format!("illegal move {}", move_str));
+    }
+    Ok(mov)
+}
+/// The history of a game starting at `pos`. Odd lengths mean white is to move,
+/// like after startpos, so a position with black to move is preceded by a filler.
+pub fn start_history(pos: Position) -> Vec<Position> {
+    if get_color(&pos) == 0 {
+        vec![pos]
+    } else {
+        vec![pos.rotate(false), pos]
+    }
+}
+/// Plays the UCI `moves` after the last position of `hist` and returns the longer
+/// history, or the problem with the first move that can't be played.
+pub fn play_moves(mut hist: Vec<Position>, moves: &[&str]) -> Result<Vec<Position>, String> {
+    for mov in moves {
+        let pos = hist[hist.len() - 1];
+        hist.push(pos.domove(parse_move(mov, &pos)?));
+    }
+    Ok(hist)
+}
+/// Starts an iterative deepening search of the last position in `hist` on the
+/// engine's worker thread, printing `info` lines and finally `bestmove`, with the
+/// expected reply as ponder move if `show_ponder` is set. The move ordering
+/// statistics are only printed with `show_ordering`.
+pub fn go_loop(
+    engine: &mut Engine,
+    hist: &[Position],
+    limits: Limits,
+    ponder: bool,
+    show_ponder: bool,
+    show_ordering: bool,
+    debug: bool,
+) {
+    if debug {
+        println!(
+            "Going movetime={:?}, clock={:?}, depth={:?}, infinite={}, ponder={}",
+            limits.movetime, limits.clock, limits.depth, limits.infinite, ponder
+        );
+    }
+    engine.set_history(hist.to_vec());
+    engine.go(
+        limits,
+        ponder,
+        move |info| {
+            if show_ordering || !matches!(info.kind, InfoKind::Ordering(_)) {
+                println!("{info}")
+            }
+        },
+        move |pv| print_bestmove(pv, show_ponder),
+    );
+}
+/// Searches the last position of `hist` until it is proven a draw, or to `max_depth`,
+/// printing the probes. Returns the move in the table, or any legal move.
+pub fn draw_loop(searcher: &mut Searcher, hist: &[Position], max_depth: i32) -> Vec<String> {
+    let pos = &hist[hist.len() - 1];
+    let start = std::time::Instant::now();
+    searcher.tt.new_search();
+    searcher.nodes = 0;
+    for depth in 1..max_depth + 1 {
+        let probes = searcher.draw_probes(hist.to_vec(), depth);
+        let elapsed = std::time::Instant::now() - start;
+        for probe in &probes {
+            let bound = match probe.bound {
+                Bound::Upper => "upperbound",
+                _ => "lowerbound",
+            };
+            println!(
+                "info depth {} score {} {} time {} nodes {}",
+                depth,
+                format_score(probe.score),
+                bound,
+                (1000.0 * elapsed.as_secs_f64()).round() as u64,
+                searcher.nodes
+            );
+        }
+        if searcher.stopped() {
+            break;
+        }
+        if let [low, high] = &probes[..]
+            && low.score >= 0
+            && high.score < 1
+        {
+            break;
+        }
+    }
+    let legal = pos.legal_moves();
+    let table_move = searcher.tt.get_move(pos.key());
+    let mov = table_move
+        .filter(|m| legal.contains(m))
+        .or(legal.first().copied());
+    pv(&Vec::from_iter(mov), pos)
+}
+// Prints the bestmove command for the line a search ended with, with the expected
+// reply as ponder move if `show_ponder` is set.
+fn print_bestmove(pv: &[String], show_ponder: bool) {
+    match pv {
+        [best, reply, ..] if show_ponder => println!("bestmove {best} ponder {reply}"),
+        [best, ..] => println!("bestmove {best}"),
+        [] => println!("bestmove (none)"),
+    }
+}
+/// Prints the perft node count of each root move and the total.
+pub fn perft(pos: &Position, depth: i32) {
+    let mut total = 0;
+    for mov in pos.legal_moves() {
+        let move_uci = render_move(Some(mov), get_color(pos) == 0);
+        let cnt = pos.domove(mov).perft(depth - 1);
+        println!("{move_uci}: {cnt}");
+        total += cnt;
+    }
+    println!("Nodes searched: {}", total);
+}
+/// Runs perft with both the mailbox and the bitboard generator. Prints the counts
+/// of each root move and the total, or the first position where they disagree.
+pub fn perft_compare(pos: &Position, depth: i32) {
+    let color = get_color(pos);
+    if let Err(d) = bitboard::perft_compare(pos, depth) {
+        let path: Vec<String> = (d.path.iter().enumerate())
+            .map(|(ply, &m)| render_move(Some(m), (color + ply as i32) % 2 == 0))
+            .collect();
+        let white_pov = get_color(&d.pos) == 0;
+        let render_all = |moves: &[Move]| -> Vec<String> {
+            (moves.iter())
+                .map(|&m| render_move(Some(m), white_pov))
+                .collect()
+        };
+        println!("Generators disagree after: {}", path.join(" "));
+        if let Some(m) = d.bad_move {
+            println!(
+                "Positions differ after: {}",
+                render_move(Some(m), white_pov)
+            );
+        } else {
+            println!("Mailbox only: {}", render_all(&d.mailbox_only).join(" "));
+            println!("Bitboard only: {}", render_all(&d.bitboard_only).join(" "));
+        }
+        return;
+    }
+    let bpos = BitPosition::from(pos);
+    let (mut total, mut btotal) = (0, 0);
+    for mov in pos.legal_moves() {
+        let move_uci = render_move(Some(mov), color == 0);
+        let cnt = pos.domove(mov).perft(depth - 1);
+        let bcnt = bitboard::perft_count(&bpos.domove(mov), depth - 1);
+        println!("{move_uci}: {cnt} {bcnt}");
+        total += cnt;
+        btotal += bcnt.max(0);
+    }
+    println!("Nodes searched: {} (mailbox) {} (bitboard)", total, btotal);
+}
+// The next input line, or None once stdin is closed.
+fn input() -> Option<String> {
+    use std::io::{self, Write};
+    let mut s = String::new();
+    io::stdout().flush().ok()?;
+    match io::stdin().read_line(&mut s) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(s.trim().to_string()),
+    }
+}
+// The options shown to the GUI.
+fn options() -> Options {
+    let mut options = Options::new();
+    let spin = |default, min, max| OptionKind::Spin { default, min, max };
+    options.add("QS", spin(QS, 0, 300));
+    options.add("QS_A", spin(QS_A, 0, 300));
+    options.add("EVAL_ROUGHNESS", spin(EVAL_ROUGHNESS, 0, 50));
+    options.add("LMR", spin(LMR, 0, 64));
+    options.add("LMP", spin(LMP, 0, 64));
+    options.add("RFP", spin(RFP, 0, 1000));
+    options.add("Ponder", OptionKind::Check { default: false });
+    options.add("Hash", spin(DEFAULT_HASH_MB as i32, 1, 4096));
+    options.add("Clear Hash", OptionKind::Button);
+    options.add("Move Overhead", spin(10, 0, 5000));
+    options.add("MultiPV", spin(1, 1, 256));
+    options.add("Ordering Stats", OptionKind::Check { default: false });
+    let algorithms = ["MTD-bi", "PVS"].map(String::from).to_vec();
+    options.add(
+        "Search",
+        OptionKind::Combo {
+            default: algorithms[0].clone(),
+            vars: algorithms,
+        },
+    );
+    options
+}
+/// Reads UCI commands from stdin until `quit`, starting from `startpos`.
+pub fn run(startpos: Position) {
+    let mut options = options();
+    let debug = false;
+    let mut hist = vec![startpos];
+    let mut engine = Engine::new();
+    loop {
+        // A closed stdin ends the session like quit.
+        let Some(line) = input() else {
+            engine.stop();
+            break;
+        };
+        if line.is_empty() {
+            continue;
+        }
+        let command = match line.parse::<UciCommand>() {
+            Ok(command) => command,
+            Err(e) => {
+                println!("info string {}", e);
+                continue;
+            }
+        };
+        // The search runs on the engine's thread, so we can always answer these.
+        match command {
+            UciCommand::Quit => {
+                engine.stop();
+                break;
+            }
+            UciCommand::Stop => {
+                engine.stop();
+            }
+            UciCommand::PonderHit => engine.ponderhit(),
+            UciCommand::Uci => {
+                println!("id name {}", VERSION);
+                for option in options.iter() {
+                    println!("{}", option);
+                }
+                println!("uciok");
+            }
+            UciCommand::SetOption { name, value } => {
+                let option = match options.set(&name, value.as_deref()) {
+                    Ok(option) => option,
+                    Err(e) => {
+                        println!("info string {}", e);
+                        continue;
+                    }
+                };
+                // Most options are only read when needed, these change the searcher right
+                // away, stopping any search. The others leave a running search alone.
+                match (option.name.as_str(), &option.value) {
+                    ("QS", &OptionValue::Spin(v)) => engine.searcher().qs = v,
+                    ("QS_A", &OptionValue::Spin(v)) => engine.searcher().qs_a = v,
+                    ("EVAL_ROUGHNESS", &OptionValue::Spin(v)) => {
+                        engine.searcher().eval_roughness = v
+                    }
+                    ("LMR", &OptionValue::Spin(v)) => engine.searcher().lmr = v,
+                    ("LMP", &OptionValue::Spin(v)) => engine.searcher().lmp = v,
+                    ("RFP", &OptionValue::Spin(v)) => engine.searcher().rfp = v,
+                    ("Hash", &OptionValue::Spin(v)) => engine.searcher().tt.resize(v as usize),
+                    ("Clear Hash", _) => engine.searcher().tt.clear(),
+                    ("Search", OptionValue::String(v)) => {
+                        engine.searcher().algorithm = match v.as_str() {
+                            "PVS" => Algorithm::Pvs,
+                            _ => Algorithm::MtdBi,
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            UciCommand::IsReady => println!("readyok"),
+            UciCommand::UciNewGame => {
+                engine.new_game();
+                hist = vec![startpos];
+            }
+            UciCommand::Position { fen: None, moves } => {
+                let moves: Vec<&str> = moves.iter().map(String::as_str).collect();
+                // A bad move rejects the whole command, keeping the previous position.
+                match play_moves(vec![startpos], &moves) {
+                    Ok(new_hist) => hist = new_hist,
+                    Err(e) => println!("info string {}", e),
+                }
+            }
+            UciCommand::Position {
+                fen: Some(fen),
+                moves,
+            } => {
+                let pos = match from_fen(&fen[0], &fen[1], &fen[2], &fen[3], &fen[4], &fen[5]) {
+                    Ok(pos) => pos,
+                    Err(e) => {
+                        println!("info string {}", e);
+                        continue;
+                    }
+                };
+                let moves: Vec<&str> = moves.iter().map(String::as_str).collect();
+                match play_moves(start_history(pos), &moves) {
+                    Ok(new_hist) => hist = new_hist,
+                    Err(e) => println!("info string {}", e),
+                }
+            }
+            UciCommand::Go(go) => {
+                let pos = hist[hist.len() - 1];
+                if let Some(depth) = go.perft {
+                    perft(&pos, depth);
+                } else if let Some(depth) = go.perftcmp {
+                    perft_compare(&pos, depth);
+                } else if let Some(max_depth) = go.draw {
+                    engine.set_history(hist.clone());
+                    engine.run(
+                        move |searcher, hist| draw_loop(searcher, hist, max_depth),
+                        |pv| print_bestmove(pv, false),
+                    );
+                } else {
+                    let mut searchmoves = Vec::new();
+                    for mov in &go.searchmoves {
+                        match parse_move(mov, &pos) {
+                            Ok(m) => searchmoves.push(m),
+                            Err(e) => println!("info string {}", e),
+                        }
+                    }
+                    // we always consider ourselves white, but uci doesn't
+                    let (time, inc) = if hist.len() % 2 == 1 {
+                        (go.wtime, go.winc)
+                    } else {
+                        (go.btime, go.binc)
+                    };
+                    let clock = time.map(|time| Clock {
+                        time,
+                        inc: inc.unwrap_or(0),
+                        movestogo: go.movestogo,
+                    });
+                    let limits = Limits {
+                        movetime: go.movetime,
+                        clock,
+                        overhead: options.spin("Move Overhead"),
+                        depth: Some(go.depth.unwrap_or(30)),
+                        nodes: go.nodes,
+                        infinite: go.infinite,
+                        searchmoves,
+                        multipv: options.spin("MultiPV") as usize,
+                        mate: go.mate,
+                    };
+                    let show_ponder = options.check("Ponder");
+                    let show_ordering = options.check("Ordering Stats");
+                    go_loop(
+                        &mut engine,
+                        &hist,
+                        limits,
+                        go.ponder,
+                        show_ponder,
+                        show_ordering,
+                        debug,
+                    );
+                }
+            }
+        }
+    }
+}
+/// A line of moves from `pos` in UCI notation, up to any move that can't be played.
+pub fn pv(line: &[Move], pos: &Position) -> Vec<String> {
+    let mut pos = *pos;
+    let mut res: Vec<String> = Vec::new();
+    for &mov in line {
+        // Only a line cut short by the table could end in a move we can't play
+        if !pos.legal_moves().contains(&mov) {
+            break;
+        }
+        res.push(render_move(Some(mov), get_color(&pos) == 0));
+        pos = pos.domove(mov);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The history after `position fen <start> moves <moves>`.
+    fn after(start: &str, moves: &[&str]) -> Result<Vec<Position>, String> {
+        play_moves(start_history(start.parse()?), moves)
+    }
+
+    #[test]
+    fn startpos_fen() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(start.parse(), Ok(Position::initial()));
+    }
+
+    #[test]
+    fn fen_after_moves() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let moves = ["e2e4", "e7e5", "g1f3"];
+        let hist = after(start, &moves).unwrap();
+        let end = "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2";
+        assert_eq!(hist.len(), 4);
+        assert_eq!(hist[3], end.parse().unwrap());
+        // The same game from the start position.
+        assert_eq!(play_moves(vec![Position::initial()], &moves), Ok(hist));
+    }
+
+    #[test]
+    fn fen_with_black_to_move() {
+        let start = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        let hist = after(start, &["c7c5", "g1f3", "d7d6"]).unwrap();
+        let end = "rnbqkbnr/pp2pppp/3p4/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3";
+        // An odd length again, with white to move.
+        assert_eq!(hist.len(), 5);
+        assert_eq!(hist[4], end.parse().unwrap());
+    }
+
+    #[test]
+    fn fen_with_en_passant() {
+        let start = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let hist = after(start, &["d4e3"]).unwrap();
+        let end = "rnbqkbnr/ppp1pppp/8/8/8/4p3/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        assert_eq!(hist[hist.len() - 1], end.parse().unwrap());
+        // Without the square the capture isn't possible.
+        let start = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(
+            after(start, &["d4e3"]),
+            Err("illegal move d4e3".to_string())
+        );
+    }
+
+    #[test]
+    fn short_and_bad_fens() {
+        // The move counters may be left out.
+        let short = after("8/8/8/8/8/8/8/K6k w - -", &["a1a2"]);
+        assert_eq!(short, after("8/8/8/8/8/8/8/K6k w - - 0 1", &["a1a2"]));
+        assert!(after("8/8/8/8/8/8/8/K6k w", &[]).is_err());
+        assert!(after("8/8/8/8/8/8/8/K6k x - - 0 1", &[]).is_err());
+        // The en passant square is behind a pawn of the side that just moved.
+        let board = "rnbqkbnr/pppp1ppp/8/3Pp3/8/8/PPP1PPPP/RNBQKBNR";
+        assert!(after(&format!("{board} w KQkq e6 0 1"), &["d5e6"]).is_ok());
+        assert!(after(&format!("{board} w KQkq d6 0 1"), &[]).is_err());
+        assert!(after(&format!("{board} w KQkq e3 0 1"), &[]).is_err());
+        assert!(after(&format!("{board} b KQkq e6 0 1"), &[]).is_err());
+        assert!(after(&format!("{board} b KQkq d3 0 1"), &[]).is_err());
+    }
+
+    #[test]
+    fn draw_search_can_be_stopped() {
+        let mut engine = Engine::new();
+        engine.run(|searcher, hist| draw_loop(searcher, hist, 100), |_| ());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let pv = engine.stop().unwrap();
+        let legal = Position::initial().legal_moves();
+        assert!(legal.iter().any(|&m| render_move(Some(m), true) == pv[0]));
+    }
+}
@@ -0,0 +1,73 @@
+//! Zobrist keys for incremental position hashing.
+//!
+//! Sunfish rotates the board after every move, so keys are taken from white's
+//! point of view: a piece is hashed by its absolute colour and square, and the
+//! side to move is a separate key. Rotating a position then only toggles [`SIDE`].
+use crate::board::{OPP, PAWN, is_opp, is_own};
+
+// splitmix64, so the tables can be built at compile time.
+const fn next(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (state, z ^ (z >> 31))
+}
+const fn table<const N: usize>(seed: u64) -> [u64; N] {
+    let mut res = [0; N];
+    let mut state = seed;
+    let mut i = 0;
+    while i < N {
+        let (s, z) = next(state);
+        state = s;
+        res[i] = z;
+        i += 1;
+    }
+    res
+}
+
+const PIECES: [u64; 12 * 120] = table(1);
+const EN_PASSANT: [u64; 120] = table(2);
+const KING_PASSANT: [u64; 120] = table(3);
+const CASTLING: [u64; 16] = table(4);
+/// Toggled whenever the side to move changes.
+pub const SIDE: u64 = table::<1>(5)[0];
+
+// A square as seen by white, given the colour (0 white, 1 black) of the side to move.
+fn absolute(color: i32, sq: usize) -> usize {
+    if color == 0 { sq } else { 119 - sq }
+}
+
+/// Key of the board code `p` on square `sq`. Empty and off-board squares hash to 0.
+pub fn piece(color: i32, sq: usize, p: u8) -> u64 {
+    let index = if is_own(p) {
+        p - PAWN
+    } else if is_opp(p) {
+        p - (PAWN | OPP) + 6
+    } else {
+        return 0;
+    } as usize;
+    // Black to move means the board has been rotated, so own pieces are black.
+    let index = if color == 0 { index } else { (index + 6) % 12 };
+    PIECES[index * 120 + absolute(color, sq)]
+}
+/// Key of the en passant square, 0 for none.
+pub fn en_passant(color: i32, ep: usize) -> u64 {
+    if ep == 0 {
+        0
+    } else {
+        EN_PASSANT[absolute(color, ep)]
+    }
+}
+/// Key of the king passant square, 0 for none.
+pub fn king_passant(color: i32, kp: usize) -> u64 {
+    if kp == 0 {
+        0
+    } else {
+        KING_PASSANT[absolute(color, kp)]
+    }
+}
+/// Key of both sides' castling rights.
+pub fn castling(wc: (bool, bool), bc: (bool, bool)) -> u64 {
+    CASTLING[wc.0 as usize | (wc.1 as usize) << 1 | (bc.0 as usize) << 2 | (bc.1 as usize) << 3]
+}